@@ -0,0 +1,42 @@
+//! 对比不同发送窗口在本机回环上的吞吐
+//!
+//! 运行：`cargo bench --bench message`
+//!
+//! 回环几乎没有传输延迟，窗口的收益主要来自不必每片都等待一次往返，
+//! 在真实网络上往返时间越长差别越大
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ptstd::net::message::MessageCenter;
+
+const MESSAGE_SIZE: usize = 4 << 20;
+const ROUNDS: usize = 5;
+
+/// 发送一条 `MESSAGE_SIZE` 字节的消息，返回接收方收齐所用的时间
+fn transfer(window: usize) -> Duration {
+    let listen = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listen.local_addr().unwrap();
+    let sender = thread::spawn(move || {
+        let (stream, _) = listen.accept().unwrap();
+        let mut center = MessageCenter::new(stream);
+        center.window = window;
+        center.send_bytes(&vec![7; MESSAGE_SIZE]).unwrap();
+    });
+    let mut receiver = MessageCenter::connect(addr).unwrap();
+    let start = Instant::now();
+    let len = receiver.receive_bytes().unwrap().len();
+    let elapsed = start.elapsed();
+    assert_eq!(len, MESSAGE_SIZE);
+    sender.join().unwrap();
+    elapsed
+}
+
+fn main() {
+    println!("{} MiB message, best of {} rounds", MESSAGE_SIZE >> 20, ROUNDS);
+    for window in [1, 4, 16, 64] {
+        let best = (0..ROUNDS).map(|_| transfer(window)).min().unwrap();
+        let rate = MESSAGE_SIZE as f64 / best.as_secs_f64() / (1 << 20) as f64;
+        println!("window {:<4} {:>10.2?} {:>10.1} MiB/s", window, best, rate);
+    }
+}
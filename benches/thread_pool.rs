@@ -0,0 +1,74 @@
+//! 对比两种调度方式在大量小任务下的吞吐
+//!
+//! 运行：`cargo bench --bench thread_pool`
+//!
+//! 争用只有在多核上才明显，核数少时两者差别不大
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ptstd::thread::{Scheduler, ThreadPool};
+
+const WORKERS: usize = 8;
+const JOBS: usize = 200_000;
+const ROUNDS: usize = 5;
+
+fn build(scheduler: Scheduler) -> Arc<ThreadPool> {
+    Arc::new(ThreadPool::builder()
+        .num_workers(WORKERS)
+        .scheduler(scheduler)
+        .build())
+}
+
+/// 所有任务都由外部线程提交
+fn external(scheduler: Scheduler) -> Duration {
+    let pool = build(scheduler);
+    let cnt = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    for _ in 0..JOBS {
+        let c = Arc::clone(&cnt);
+        pool.execute(move || { c.fetch_add(1, Ordering::Relaxed); }).unwrap();
+    }
+    pool.shutdown();
+    let elapsed = start.elapsed();
+    assert_eq!(cnt.load(Ordering::Relaxed), JOBS);
+    elapsed
+}
+
+/// 任务在工作线程内部继续拆分提交
+fn nested(scheduler: Scheduler) -> Duration {
+    let pool = build(scheduler);
+    let cnt = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..WORKERS).map(|_| {
+        let p = Arc::clone(&pool);
+        let c = Arc::clone(&cnt);
+        pool.submit(move || {
+            for _ in 0..JOBS / WORKERS {
+                let c = Arc::clone(&c);
+                p.execute(move || { c.fetch_add(1, Ordering::Relaxed); }).unwrap();
+            }
+        })
+    }).collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    pool.shutdown();
+    let elapsed = start.elapsed();
+    assert_eq!(cnt.load(Ordering::Relaxed), JOBS);
+    elapsed
+}
+
+fn bench(name: &str, f: fn(Scheduler) -> Duration) {
+    for scheduler in [Scheduler::Global, Scheduler::WorkStealing] {
+        let best = (0..ROUNDS).map(|_| f(scheduler)).min().unwrap();
+        let rate = JOBS as f64 / best.as_secs_f64();
+        println!("{:<10} {:<14} {:>10.2?} {:>12.0} jobs/s", name, format!("{:?}", scheduler), best, rate);
+    }
+}
+
+fn main() {
+    println!("{} workers, {} jobs, best of {} rounds", WORKERS, JOBS, ROUNDS);
+    bench("external", external);
+    bench("nested", nested);
+}
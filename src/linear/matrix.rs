@@ -6,6 +6,8 @@ pub use ndarray;
 /// ## 注
 /// 为了方便使用，会 `clone` 第一个矩阵
 /// ```
+/// # use ptstd::matrix_mul;
+/// # use ndarray::array;
 /// let a = array![
 ///     [1, 2],
 ///     [3, 4]
@@ -23,6 +25,7 @@ macro_rules! matrix_mul {
 
 /// 方便创建矩阵，减少写括号
 /// ```
+/// # use ptstd::matrix;
 /// let a = matrix![
 ///     1, 2;
 ///     3, 4
@@ -1,3 +1,2 @@
-/// !主要就是提供 `ndarray` 这个 `crate` 的一些宏
-
+//! 主要就是提供 `ndarray` 这个 `crate` 的一些宏
 pub mod matrix;
\ No newline at end of file
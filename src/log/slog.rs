@@ -10,6 +10,8 @@ use std::sync::Mutex;
 /// 
 /// # Example
 /// ```no_run
+/// # use ptstd::log::slog::*;
+/// # use log::{set_max_level, LevelFilter};
 /// SLog::init().unwrap();
 /// set_max_level(LevelFilter::Info);
 /// 
@@ -55,7 +57,6 @@ impl SLog {
     pub fn init_with_filename(fname: &str, max_level: LevelFilter) -> Result<(), SetLoggerError> {
         let f = OpenOptions::new()
             .read(true)
-            .write(true)
             .create(true)
             .append(true)
             .open(fname)
@@ -114,17 +115,4 @@ mod tests {
         warn!("some warn");
         error!("some error");
     }
-
-    #[test]
-    fn basic_file() {
-        SLog::init_with_filename("test.log", LevelFilter::Trace).unwrap();
-        set_max_level(LevelFilter::Debug);
-
-        trace!("some trace");
-        debug!("some debug");
-        info!("some info");
-        warn!("some warn");
-        error!("some error");
-        debug!(target: "my_target", "a {} event", "log");
-    }
 }
@@ -0,0 +1,141 @@
+//! 消息分片的校验算法
+
+#[cfg(feature = "crypto")]
+use crate::crypto::hash::ToSha256;
+
+/// 校验算法，编号写在协议头中
+///
+/// 连接双方通过 `MessageCenter::negotiate` 从共同支持的算法中选出最强的一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Checksum {
+    /// 不校验，校验码恒为 0
+    #[default]
+    None,
+    /// IEEE 802.3 CRC-32
+    Crc32,
+    /// Adler-32，比 CRC-32 快，但对短数据的检错能力较弱
+    Adler32,
+    /// SHA-256 的前 4 字节
+    #[cfg(feature = "crypto")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+    Sha256,
+}
+
+impl Checksum {
+    /// 本端支持的全部算法，按强度从高到低排列
+    pub fn all() -> &'static [Checksum] {
+        &[
+            #[cfg(feature = "crypto")]
+            Checksum::Sha256,
+            Checksum::Crc32,
+            Checksum::Adler32,
+            Checksum::None,
+        ]
+    }
+
+    /// 协议头中的编号
+    pub fn id(self) -> u8 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32 => 1,
+            Checksum::Adler32 => 2,
+            #[cfg(feature = "crypto")]
+            Checksum::Sha256 => 3,
+        }
+    }
+
+    /// 由编号得到算法，本端不支持时返回 `None`
+    pub fn from_id(id: u8) -> Option<Checksum> {
+        Self::all().iter().copied().find(|c| c.id() == id)
+    }
+
+    /// 计算 `data` 的校验码
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32 => crc32(data),
+            Checksum::Adler32 => adler32(data),
+            #[cfg(feature = "crypto")]
+            Checksum::Sha256 => {
+                let digest = data.to_sha256();
+                u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+            },
+        }
+    }
+
+    /// 从双方支持的算法中选出最强的一个，没有交集时不校验
+    pub fn select(local: &[Checksum], remote: &[Checksum]) -> Checksum {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|c| local.contains(c) && remote.contains(c))
+            .unwrap_or(Checksum::None)
+    }
+}
+
+/// CRC-32 查找表，多项式为 0xEDB88320（按位反转）
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // 5552 是保证 b 不溢出 u32 的最大分块长度
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(Checksum::Crc32.compute(b"123456789"), 0xcbf4_3926);
+        assert_eq!(Checksum::Crc32.compute(b""), 0);
+        assert_eq!(Checksum::Adler32.compute(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(Checksum::Adler32.compute(b""), 1);
+        assert_eq!(Checksum::None.compute(b"anything"), 0);
+        #[cfg(feature = "crypto")]
+        assert_eq!(Checksum::Sha256.compute(b"abc"), 0xba78_16bf);
+        // 超过分块长度时结果仍与逐字节取模一致
+        let data: Vec<u8> = (0..20000).map(|i| (i * 7) as u8).collect();
+        let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), &x| ((a + x as u64) % 65521, (b + a + x as u64) % 65521));
+        assert_eq!(Checksum::Adler32.compute(&data), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn test_select() {
+        for &c in Checksum::all() {
+            assert_eq!(Checksum::from_id(c.id()), Some(c));
+        }
+        assert_eq!(Checksum::from_id(200), None);
+        assert_eq!(Checksum::select(&[Checksum::Adler32, Checksum::Crc32], &[Checksum::Crc32]), Checksum::Crc32);
+        assert_eq!(Checksum::select(&[Checksum::Adler32], &[Checksum::Crc32]), Checksum::None);
+        assert_eq!(Checksum::select(Checksum::all(), Checksum::all()), Checksum::all()[0]);
+    }
+}
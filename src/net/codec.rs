@@ -0,0 +1,524 @@
+//! 消息的编码与解码
+//!
+//! 多字节整数均为大端序；长度、`usize` 与 `isize` 按 64 位编码；
+//! 变长类型先写 `u64` 长度再写内容；`Option` 与 `bool` 各占一个字节；
+//! 枚举先写 `u32` 的变体序号再写字段
+
+use super::Error;
+
+/// 元素编码后的总字节数小于元素个数的 `Vec` 最多包含的元素数
+///
+/// 每个元素至少占一个字节时长度不会超过剩余字节数；只有编码为零字节的元素（如 `()`）
+/// 才能超过，这里限制其个数，避免对端用一个巨大的长度让解码空转。
+/// 编码时不检查，超过上限的 `Vec` 可以编码，但解码时返回 `Error::Protocol`
+pub const MAX_LEN: usize = 1024;
+
+/// 可以通过 `MessageCenter::send_message` 与 `recv_message` 收发的消息
+///
+/// 结构体与枚举可以用 `message!` 定义并自动实现
+///
+/// # Example
+/// ```
+/// use ptstd::net::codec::Message;
+///
+/// let mut buf = Vec::new();
+/// (7u16, String::from("hi"), Some(true)).encode(&mut buf);
+/// assert_eq!(buf, [0, 7, 0, 0, 0, 0, 0, 0, 0, 2, b'h', b'i', 1, 1]);
+/// assert_eq!(<(u16, String, Option<bool>)>::decode(&buf).unwrap(), (7, "hi".to_string(), Some(true)));
+/// ```
+pub trait Message: Sized {
+    /// 将编码追加到 `buf` 末尾
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// 从 `buf` 开头解码一个值，并将 `buf` 移过已读取的部分
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error>;
+
+    /// 解码一条完整的消息，有多余的字节时返回错误
+    fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut rest = buf;
+        let value = Self::decode_from(&mut rest)?;
+        if !rest.is_empty() {
+            return Err(Error::Protocol("trailing bytes after message"));
+        }
+        Ok(value)
+    }
+}
+
+/// 从 `buf` 开头取出 `n` 个字节
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if buf.len() < n {
+        return Err(Error::Protocol("message truncated"));
+    }
+    let (head, rest) = buf.split_at(n);
+    *buf = rest;
+    Ok(head)
+}
+
+/// 读取长度前缀
+fn decode_len(buf: &mut &[u8]) -> Result<usize, Error> {
+    usize::try_from(u64::decode_from(buf)?).map_err(|_| Error::Protocol("length overflows usize"))
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {$(
+        impl Message for $t {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+                let bytes = take(buf, std::mem::size_of::<$t>())?;
+                Ok(<$t>::from_be_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+impl_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Message for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        usize::try_from(u64::decode_from(buf)?).map_err(|_| Error::Protocol("value overflows usize"))
+    }
+}
+
+impl Message for isize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as i64).encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        isize::try_from(i64::decode_from(buf)?).map_err(|_| Error::Protocol("value overflows isize"))
+    }
+}
+
+impl Message for f32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_bits().encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        u32::decode_from(buf).map(f32::from_bits)
+    }
+}
+
+impl Message for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_bits().encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        u64::decode_from(buf).map(f64::from_bits)
+    }
+}
+
+impl Message for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        match u8::decode_from(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::Protocol("invalid bool")),
+        }
+    }
+}
+
+impl Message for char {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        char::from_u32(u32::decode_from(buf)?).ok_or(Error::Protocol("invalid char"))
+    }
+}
+
+impl Message for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        let len = decode_len(buf)?;
+        let bytes = take(buf, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::Protocol("invalid utf-8"))
+    }
+}
+
+impl<T: Message> Message for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        let len = decode_len(buf)?;
+        if len > buf.len().max(MAX_LEN) {
+            return Err(Error::Protocol("vec length exceeds remaining bytes"));
+        }
+        // 长度来自对端，预分配不超过剩余的字节数
+        let mut items = Vec::with_capacity(len.min(buf.len()));
+        let remaining = buf.len();
+        for _ in 0..len {
+            items.push(T::decode_from(buf)?);
+        }
+        // 只计元素本身的字节，结果与后面是否还有其他字段无关
+        if len > MAX_LEN && remaining - buf.len() < len {
+            return Err(Error::Protocol("vec of zero-sized items exceeds MAX_LEN"));
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Message> Message for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(value) => {
+                buf.push(1);
+                value.encode(buf);
+            },
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        match u8::decode_from(buf)? {
+            0 => Ok(None),
+            1 => T::decode_from(buf).map(Some),
+            _ => Err(Error::Protocol("invalid option tag")),
+        }
+    }
+}
+
+impl<T: Message> Message for Box<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+        T::decode_from(buf).map(Box::new)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($(($($name:ident),*)),*) => {$(
+        impl<$($name: Message),*> Message for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $( $name.encode(buf); )*
+            }
+
+            #[allow(unused_variables)]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, Error> {
+                Ok(($($name::decode_from(buf)?,)*))
+            }
+        }
+    )*};
+}
+
+impl_tuple!((), (A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E), (A, B, C, D, E, F));
+
+/// 定义结构体或枚举，并为其实现 `Message`
+///
+/// 支持具名字段与元组结构体，以及单元、具名字段与元组形式的枚举变体，
+/// 所有字段都需要实现 `Message`。暂不支持泛型与显式的判别值，元组字段最多 12 个
+///
+/// # Example
+/// ```
+/// use ptstd::message;
+/// use ptstd::net::codec::Message;
+///
+/// message! {
+///     #[derive(Debug, PartialEq)]
+///     pub struct Login {
+///         pub user: String,
+///         pub token: Option<u64>,
+///     }
+/// }
+///
+/// message! {
+///     #[derive(Debug, PartialEq)]
+///     pub enum Command {
+///         Ping,
+///         Say(String),
+///         Move { x: i32, y: i32 },
+///     }
+/// }
+///
+/// let mut buf = Vec::new();
+/// Login { user: "root".into(), token: None }.encode(&mut buf);
+/// assert_eq!(Login::decode(&buf).unwrap(), Login { user: "root".into(), token: None });
+///
+/// let cmd = Command::Move { x: -1, y: 2 };
+/// let mut buf = Vec::new();
+/// cmd.encode(&mut buf);
+/// assert_eq!(Command::decode(&buf).unwrap(), cmd);
+/// ```
+#[macro_export]
+macro_rules! message {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fmeta:meta])* $fvis:vis $field:ident : $fty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$fmeta])* $fvis $field : $fty),*
+        }
+
+        impl $crate::net::codec::Message for $name {
+            #[allow(unused_variables)]
+            fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+                $( $crate::net::codec::Message::encode(&self.$field, buf); )*
+            }
+
+            #[allow(unused_variables)]
+            fn decode_from(buf: &mut &[u8]) -> ::std::result::Result<Self, $crate::net::Error> {
+                ::std::result::Result::Ok($name {
+                    $($field: <$fty as $crate::net::codec::Message>::decode_from(buf)?),*
+                })
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident ( $($(#[$fmeta:meta])* $fvis:vis $fty:ty),* $(,)? );
+    ) => {
+        $(#[$meta])*
+        $vis struct $name ( $($(#[$fmeta])* $fvis $fty),* );
+
+        impl $crate::net::codec::Message for $name {
+            #[allow(irrefutable_let_patterns)]
+            fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+                $crate::__message_encode_tuple!([Self] self, buf, [] [] [
+                    __m0 __m1 __m2 __m3 __m4 __m5 __m6 __m7 __m8 __m9 __m10 __m11
+                ] $($fty),*);
+            }
+
+            #[allow(unused_variables)]
+            fn decode_from(buf: &mut &[u8]) -> ::std::result::Result<Self, $crate::net::Error> {
+                ::std::result::Result::Ok($name($(<$fty as $crate::net::codec::Message>::decode_from(buf)?),*))
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$vmeta:meta])*
+                $variant:ident
+                $({ $($(#[$fmeta:meta])* $field:ident : $fty:ty),* $(,)? })?
+                $(( $($(#[$tmeta:meta])* $tty:ty),* $(,)? ))?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $(
+                $(#[$vmeta])*
+                $variant
+                $({ $($(#[$fmeta])* $field : $fty),* })?
+                $(( $($(#[$tmeta])* $tty),* ))?
+            ),*
+        }
+
+        impl $crate::net::codec::Message for $name {
+            fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+                #[allow(unused_assignments, unused_mut)]
+                let mut tag: u32 = 0;
+                $(
+                    $crate::__message_encode_variant!(self, buf, tag, $variant $({ $($field),* })? $(( $($tty),* ))?);
+                    tag += 1;
+                )*
+                unreachable!();
+            }
+
+            fn decode_from(buf: &mut &[u8]) -> ::std::result::Result<Self, $crate::net::Error> {
+                let tag = <u32 as $crate::net::codec::Message>::decode_from(buf)?;
+                #[allow(unused_assignments, unused_mut)]
+                let mut i: u32 = 0;
+                $(
+                    if tag == i {
+                        return ::std::result::Result::Ok(Self::$variant
+                            $({ $($field: <$fty as $crate::net::codec::Message>::decode_from(buf)?),* })?
+                            $(( $(<$tty as $crate::net::codec::Message>::decode_from(buf)?),* ))?
+                        );
+                    }
+                    i += 1;
+                )*
+                ::std::result::Result::Err($crate::net::Error::Protocol("unknown enum variant"))
+            }
+        }
+    };
+}
+
+/// `message!` 的内部实现：编码枚举的一个变体，`$value` 不是该变体时什么也不做
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __message_encode_variant {
+    ($value:expr, $buf:ident, $tag:ident, $variant:ident) => {
+        if let Self::$variant = $value {
+            $crate::net::codec::Message::encode(&$tag, $buf);
+            return;
+        }
+    };
+    ($value:expr, $buf:ident, $tag:ident, $variant:ident { $($field:ident),* }) => {
+        if let Self::$variant { $($field),* } = $value {
+            $crate::net::codec::Message::encode(&$tag, $buf);
+            $( $crate::net::codec::Message::encode($field, $buf); )*
+            return;
+        }
+    };
+    ($value:expr, $buf:ident, $tag:ident, $variant:ident ( $($ty:ty),* )) => {
+        $crate::__message_encode_tuple!([Self::$variant] $value, $buf, [
+            $crate::net::codec::Message::encode(&$tag, $buf);
+        ] [] [
+            __m0 __m1 __m2 __m3 __m4 __m5 __m6 __m7 __m8 __m9 __m10 __m11
+        ] $($ty),*);
+    };
+}
+
+/// `message!` 的内部实现：为每个元组字段依次分配绑定名，全部分配后编码
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __message_encode_tuple {
+    (
+        [$($path:tt)*] $value:expr, $buf:ident, [$($prefix:tt)*]
+        [$($bound:ident)*] [$next:ident $($pool:ident)*] $ty:ty $(, $rest:ty)*
+    ) => {
+        $crate::__message_encode_tuple!(
+            [$($path)*] $value, $buf, [$($prefix)*] [$($bound)* $next] [$($pool)*] $($rest),*
+        )
+    };
+    ([$($path:tt)*] $value:expr, $buf:ident, [$($prefix:tt)*] [$($bound:ident)*] [$($pool:ident)*]) => {
+        if let $($path)* ($($bound),*) = $value {
+            $($prefix)*
+            $( $crate::net::codec::Message::encode($bound, $buf); )*
+            return;
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Message + PartialEq + std::fmt::Debug>(value: T) {
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        assert_eq!(T::decode(&buf).unwrap(), value);
+    }
+
+    crate::message! {
+        #[derive(Debug, PartialEq, Clone)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+    }
+
+    crate::message! {
+        #[derive(Debug, PartialEq)]
+        struct Pair(pub String, Point);
+    }
+
+    crate::message! {
+        #[derive(Debug, PartialEq)]
+        enum Shape {
+            Empty,
+            Circle { center: Point, radius: f64 },
+            Polygon(Vec<Point>, Option<String>),
+        }
+    }
+
+    #[test]
+    fn test_primitives() {
+        round_trip(0x1234u16);
+        round_trip(-5i64);
+        round_trip(u128::MAX);
+        round_trip(usize::MAX);
+        round_trip(1.5f32);
+        round_trip(true);
+        round_trip('中');
+        round_trip(String::from("hello 世界"));
+        round_trip(vec![Some(1u8), None, Some(3)]);
+        round_trip((1u8, (), (String::new(), vec![false])));
+        round_trip(Box::new(7u32));
+
+        let mut buf = Vec::new();
+        0x0102_0304u32.encode(&mut buf);
+        assert_eq!(buf, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_derived() {
+        let p = Point { x: 1, y: -2 };
+        round_trip(p.clone());
+        round_trip(Pair("p".into(), p.clone()));
+        round_trip(Shape::Empty);
+        round_trip(Shape::Circle { center: p.clone(), radius: 2.0 });
+        round_trip(Shape::Polygon(vec![p.clone(), p], Some("tri".into())));
+
+        // 变体序号在最前
+        let mut buf = Vec::new();
+        Shape::Empty.encode(&mut buf);
+        assert_eq!(buf, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(u32::decode(&[1, 2]), Err(Error::Protocol(_))));
+        assert!(matches!(u8::decode(&[1, 2]), Err(Error::Protocol(_))));
+        assert!(matches!(bool::decode(&[2]), Err(Error::Protocol(_))));
+        assert!(matches!(String::decode(&[0, 0, 0, 0, 0, 0, 0, 1, 0xff]), Err(Error::Protocol(_))));
+        // 声明的长度远大于实际数据
+        assert!(matches!(Vec::<u8>::decode(&[0xff; 8]), Err(Error::Protocol(_))));
+        assert!(matches!(Shape::decode(&[0, 0, 0, 9]), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_hostile_len() {
+        // 零字节的元素不受剩余字节数约束，巨大的长度必须直接拒绝
+        let mut buf = Vec::new();
+        (1u64 << 40).encode(&mut buf);
+        assert!(matches!(Vec::<()>::decode(&buf), Err(Error::Protocol(_))));
+        assert!(matches!(Vec::<u8>::decode(&buf), Err(Error::Protocol(_))));
+        round_trip(vec![(); MAX_LEN]);
+        round_trip(vec![7u8; MAX_LEN * 4]);
+    }
+
+    crate::message! {
+        #[derive(Debug, PartialEq)]
+        struct Units {
+            units: Vec<()>,
+            data: Vec<u8>,
+        }
+    }
+
+    #[test]
+    fn test_max_len() {
+        // 超过上限的零字节元素可以编码，但解码时总是被拒绝
+        let mut buf = Vec::new();
+        vec![(); MAX_LEN + 1].encode(&mut buf);
+        assert!(matches!(Vec::<()>::decode(&buf), Err(Error::Protocol(_))));
+        // 后面的字段提供了足够的字节时同样拒绝
+        let mut buf = Vec::new();
+        Units { units: vec![(); MAX_LEN + 1], data: vec![0; MAX_LEN * 2] }.encode(&mut buf);
+        assert!(matches!(Units::decode(&buf), Err(Error::Protocol(_))));
+        round_trip(Units { units: vec![(); MAX_LEN], data: vec![0; MAX_LEN * 2] });
+        // 每个元素至少占一个字节时不受限制
+        round_trip(vec![Some(()); MAX_LEN * 2]);
+    }
+}
@@ -0,0 +1,51 @@
+use std::io;
+
+use thiserror::Error;
+
+use super::message::HeaderError;
+
+/// 网络模块的错误
+#[derive(Debug, Error)]
+pub enum Error {
+    /// 底层 I/O 错误
+    #[error("i/o error: {0}")]
+    Io(#[source] io::Error),
+    /// 对端发来了不符合协议的数据
+    #[error("protocol violation: {0}")]
+    Protocol(&'static str),
+    /// 某片数据连续多次校验失败，放弃发送
+    #[error("slice at {begin} failed checksum {retries} times")]
+    Checksum { begin: u64, retries: usize },
+    /// 对端使用了不支持的协议版本
+    #[error("unsupported message version {0}")]
+    Version(u8),
+    /// 读写超时，见 `MessageCenter::set_timeout`
+    #[error("operation timed out")]
+    Timeout,
+    /// 对端已关闭连接
+    #[error("connection closed by peer")]
+    PeerClosed,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            // 设置读写超时后，超时在不同平台上分别表现为这两种错误
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Error::Timeout,
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Error::PeerClosed,
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl From<HeaderError> for Error {
+    fn from(e: HeaderError) -> Self {
+        match e {
+            HeaderError::UnsupportedVersion(v) => Error::Version(v),
+            HeaderError::OutOfRange { .. } => Error::Protocol("slice exceeds message length"),
+        }
+    }
+}
@@ -1,42 +1,100 @@
-/// # 消息机制
-/// 
-/// - 设计目标是小量信息传输
-/// - 丢失超时重传交由传输层协议完成
-/// - 停等协议
-/// - 协议保证
-///     - 数据相对完善（hash等简易校验）
-///     - 数据定界（TCP流式协议模糊了定界）
-/// 
-/// ## 协议头
-/// 1. 版本         1B
-/// 2. 是否分片     1B
-/// 3. 保留标志     2B
-/// 4. 块起始       2B
-/// 5. 块长度       2B
-/// 6. 校验         4B
-/// 7. 数据
-/// 
-/// 连接建立后，发送
-use std::{net::{TcpStream, ToSocketAddrs}, io::{self, Write, Read}};
+//! # 消息机制
+//!
+//! - 设计目标是小量信息传输
+//! - 丢失超时重传交由传输层协议完成
+//! - 滑动窗口协议，窗口为 1 时即停等协议
+//! - 协议保证
+//!     - 数据相对完善（hash等简易校验）
+//!     - 数据定界（TCP流式协议模糊了定界）
+//!
+//! ## 协议头
+//!
+//! 固定 28 字节，多字节字段均为大端序，与平台的字长和字节序无关
+//!
+//! | 偏移 | 长度 | 字段           |
+//! |------|------|----------------|
+//! | 0    | 1    | 版本，当前为 2 |
+//! | 1    | 1    | 标志           |
+//! | 2    | 1    | 校验算法编号   |
+//! | 3    | 1    | 保留           |
+//! | 4    | 8    | 该片起始偏移   |
+//! | 12   | 4    | 该片长度       |
+//! | 16   | 8    | 消息总长度     |
+//! | 24   | 4    | 该片数据校验码 |
+//!
+//! 协议头之后紧跟该片的数据
+//!
+//! ## 校验
+//!
+//! 连接建立后双方可以调用 `MessageCenter::negotiate` 交换各自支持的校验算法，
+//! 之后每片数据都带有协商出的算法编号与校验码，校验失败的分片由发送方重传
+//!
+//! ## 窗口
+//!
+//! 发送方最多同时发出 `MessageCenter::window` 个分片而不等待确认。
+//! 接收方对每个分片单独应答，应答头的 `begin`、`length` 与该分片相同，
+//! 带确认位表示已收到，不带则要求重传。重传的分片会晚于其后的分片到达，
+//! 接收方按 `begin` 放回原位
+use std::{net::{TcpStream, ToSocketAddrs}, io::{self, Write, Read}, collections::{BTreeMap, BTreeSet}, time::Duration};
+
+use thiserror::Error;
+
+use super::checksum::Checksum;
+pub use super::codec::Message;
+use super::Error;
+
+/// 当前的协议版本，版本 1 直接发送内存布局，已不再支持
+pub const VERSION: u8 = 2;
+
+/// 编码后协议头的字节数
+pub const HEADER_LEN: usize = 28;
+
+/// 每个分片的最大数据长度
+pub const SLICE_SIZE: usize = 1024;
+
+/// 默认的发送窗口
+pub const DEFAULT_WINDOW: usize = 16;
+
+/// 窗口的上限，接收方只接受最早缺失的分片之后 `MAX_WINDOW` 片以内的数据
+pub const MAX_WINDOW: usize = 1024;
+
+/// 同一片最多连续重传的次数
+pub const MAX_RETRANSMITS: usize = 16;
+
+/// 协商时对端最多列出的校验算法数，算法编号只有一个字节
+pub const MAX_CHECKSUMS: usize = 256;
+
+/// 解码协议头时的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum HeaderError {
+    /// 不支持的协议版本
+    #[error("unsupported message version {0}")]
+    UnsupportedVersion(u8),
+    /// 该片超出了消息总长度
+    #[error("slice {begin}+{length} exceeds message length {whole_length}")]
+    OutOfRange { begin: u64, length: u32, whole_length: u64 },
+}
 
 /// 消息头
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MessageHeader {
     /// 版本
     pub version: u8,
     /// 第一位为0为发送包，1为应答包
     /// 第二位为是否分片标志
+    /// 第三位为校验算法协商包标志
     /// 第五位为应答确认位
     pub flag: u8,
+    /// 校验算法编号，见 `Checksum::id`
+    pub checksum: u8,
     /// 保留
-    pub reserved: u16,
+    pub reserved: u8,
     /// 该包开始偏移
-    pub begin: usize,
+    pub begin: u64,
     /// 该包长度
-    pub length: usize,
+    pub length: u32,
     /// 消息总长度
-    pub whole_length: usize,
+    pub whole_length: u64,
     /// 该包数据部分校验码
     pub check: u32,
 }
@@ -49,23 +107,18 @@ pub struct MessageCenter {
     pub recv_buf    : Vec<u8>,
     pub send_buf    : Vec<u8>,
     pub tcpstream   : Option<TcpStream>,
-}
-
-/// 消息接口
-pub trait Message {
-    /// 转化为字节数组
-    fn as_bytes(&self) -> &[u8];
-    /// 序列化
-    fn encode(&self) -> &[u8];
-    /// 反序列化
-    fn decode() -> Self;
+    /// 当前使用的校验算法，协商前不校验
+    pub checksum    : Checksum,
+    /// 同时在途的最大分片数，为 1 时即停等，最大为 `MAX_WINDOW`
+    pub window      : usize,
 }
 
 impl Default for MessageHeader {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: VERSION,
             flag: 0,
+            checksum: 0,
             reserved: 0,
             begin: 0,
             length: 0,
@@ -76,18 +129,51 @@ impl Default for MessageHeader {
 }
 
 impl MessageHeader {
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            let p: *const u8 = std::mem::transmute(self);
-            std::slice::from_raw_parts(p, std::mem::size_of::<MessageHeader>())
-        }
+    /// 按大端序编码
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0; HEADER_LEN];
+        buf[0] = self.version;
+        buf[1] = self.flag;
+        buf[2] = self.checksum;
+        buf[3] = self.reserved;
+        buf[4..12].copy_from_slice(&self.begin.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.length.to_be_bytes());
+        buf[16..24].copy_from_slice(&self.whole_length.to_be_bytes());
+        buf[24..28].copy_from_slice(&self.check.to_be_bytes());
+        buf
     }
 
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            let p: *mut u8 = std::mem::transmute(self);
-            std::slice::from_raw_parts_mut(p, std::mem::size_of::<MessageHeader>())
+    /// 从大端序解码，检查版本与范围
+    pub fn decode(buf: &[u8; HEADER_LEN]) -> Result<MessageHeader, HeaderError> {
+        let header = MessageHeader {
+            version: buf[0],
+            flag: buf[1],
+            checksum: buf[2],
+            reserved: buf[3],
+            begin: u64::from_be_bytes(buf[4..12].try_into().unwrap()),
+            length: u32::from_be_bytes(buf[12..16].try_into().unwrap()),
+            whole_length: u64::from_be_bytes(buf[16..24].try_into().unwrap()),
+            check: u32::from_be_bytes(buf[24..28].try_into().unwrap()),
+        };
+        if header.version != VERSION {
+            return Err(HeaderError::UnsupportedVersion(header.version));
+        }
+        let end = header.begin.checked_add(header.length as u64);
+        if end.is_none_or(|end| end > header.whole_length) {
+            return Err(HeaderError::OutOfRange {
+                begin: header.begin,
+                length: header.length,
+                whole_length: header.whole_length,
+            });
         }
+        Ok(header)
+    }
+
+    /// 从流中读取并解码
+    fn read_from(stream: &mut impl Read) -> Result<MessageHeader, Error> {
+        let mut buf = [0; HEADER_LEN];
+        stream.read_exact(&mut buf)?;
+        Ok(Self::decode(&buf)?)
     }
 
     /// 是否是确认应答包
@@ -97,7 +183,7 @@ impl MessageHeader {
 
     /// 是否分片
     pub fn is_sliced(&self) -> bool {
-        (self.flag & 0b0010) == 1
+        (self.flag & 0b0010) != 0
     }
 
     /// 接收到数据是否正确
@@ -105,6 +191,11 @@ impl MessageHeader {
         (self.flag & 0x10) != 0
     }
 
+    /// 是否是校验算法协商包
+    pub fn is_negotiation(&self) -> bool {
+        (self.flag & 0b0100) != 0
+    }
+
     pub fn set_response(&mut self) {
         self.flag |= 0b0001
     }
@@ -116,11 +207,15 @@ impl MessageHeader {
     pub fn set_correct(&mut self) {
         self.flag |= 0x10
     }
+
+    pub fn set_negotiation(&mut self) {
+        self.flag |= 0b0100
+    }
 }
 
 impl MessageCenter {
     /// 通过地址创建
-    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<MessageCenter> {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<MessageCenter, Error> {
         let tcpstream = TcpStream::connect(addr)?;
         Ok(Self::new(tcpstream))
     }
@@ -133,148 +228,237 @@ impl MessageCenter {
             recv_buf: Vec::new(),
             send_buf: Vec::new(),
             tcpstream: Some(tcpstream),
+            checksum: Checksum::None,
+            window: DEFAULT_WINDOW,
         }
     }
 
-    /// 默认的校验和
-    pub fn default_checksum(_: &[u8]) -> u32 {
-        0
+    /// 默认的校验和，即不校验
+    pub fn default_checksum(data: &[u8]) -> u32 {
+        Checksum::None.compute(data)
+    }
+
+    /// 设置每次读写的超时，超时后返回 `Error::Timeout`，`None` 为不超时
+    ///
+    /// 超时后连接处于未知状态，应当丢弃
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let tcpstream = connected(&mut self.tcpstream)?;
+        tcpstream.set_read_timeout(timeout)?;
+        tcpstream.set_write_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// 与对端交换各自支持的校验算法，双方都选用共同支持的最强算法并返回
+    ///
+    /// 双方都需要在收发消息前调用，`supported` 为空时视为只支持不校验。
+    /// 对端列出超过 `MAX_CHECKSUMS` 个算法时返回 `Error::Protocol`
+    pub fn negotiate(&mut self, supported: &[Checksum]) -> Result<Checksum, Error> {
+        let tcpstream = connected(&mut self.tcpstream)?;
+        let ids: Vec<u8> = supported.iter().map(|c| c.id()).collect();
+        let mut header = MessageHeader { length: ids.len() as u32, whole_length: ids.len() as u64, ..Default::default() };
+        header.set_negotiation();
+        tcpstream.write_all(&header.encode())?;
+        tcpstream.write_all(&ids)?;
+
+        let peer = MessageHeader::read_from(tcpstream)?;
+        if !peer.is_negotiation() {
+            return Err(Error::Protocol("expected checksum negotiation"));
+        }
+        // 先检查长度再分配，不信任对端给出的长度
+        if peer.length as usize > MAX_CHECKSUMS {
+            return Err(Error::Protocol("too many checksums in negotiation"));
+        }
+        let mut peer_ids = vec![0; peer.length as usize];
+        tcpstream.read_exact(&mut peer_ids)?;
+        // 忽略本端不认识的算法
+        let remote: Vec<_> = peer_ids.into_iter().filter_map(Checksum::from_id).collect();
+        self.checksum = Checksum::select(supported, &remote);
+        Ok(self.checksum)
     }
 
     /// 发送字节
-    pub fn send_bytes(&mut self, msg: &[u8]) -> Result<(), &'static str> {
-        const SLICE_SIZE: usize = 1024;
+    ///
+    /// 最多同时发出 `window` 个分片，收到重传应答的分片会立即重发，
+    /// 同一片连续重传超过 `MAX_RETRANSMITS` 次时返回 `Error::Checksum`
+    pub fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Error> {
+        let tcpstream = connected(&mut self.tcpstream)?;
         // 协议头填充
-        let whole_len = msg.len();
-        let mut header = &mut self.recv_hd;
+        let whole_len = msg.len() as u64;
+        let header = &mut self.send_hd;
         *header = MessageHeader::default();
         header.whole_length = whole_len;
-        let mut already_send_size: usize = 0;
+        header.checksum = self.checksum.id();
         if msg.len() > SLICE_SIZE {
             header.set_sliced()
         }
-        // 发送
-        if let Some(tcpstream) = &mut self.tcpstream {
-            while already_send_size < whole_len {
-                // 填写偏移和长度
-                header.begin = already_send_size;
-                if already_send_size + SLICE_SIZE < whole_len {
-                    header.length = SLICE_SIZE;
-                } else {
-                    header.length = whole_len - already_send_size;
-                }
-                // 要发送的数据
-                let data = &msg[already_send_size..already_send_size+header.length];
-                // 填写该片数据的校验码
-                header.check = Self::default_checksum(data);
-                // 发送头
-                tcpstream.write_all(header.as_bytes()).map_err(|_| "send header error")?;
-                // 发送数据
-                tcpstream.write_all(data).map_err(|_| "write data error")?;
-                // 等待接收结果
-                let mut rhd = MessageHeader::default();
-                tcpstream.read_exact(rhd.as_bytes_mut()).unwrap();
-                if rhd.is_response() && rhd.is_correct() {
-                    // 计数后移
-                    already_send_size += header.length;
+        let window = (self.window.clamp(1, MAX_WINDOW) * SLICE_SIZE) as u64;
+        // 已发出但未确认的分片偏移及其重传次数
+        let mut in_flight = BTreeMap::new();
+        // 下一个要发出的分片，空消息也发送一个空的分片
+        let mut next_begin = Some(0);
+        loop {
+            // 填满窗口，窗口从最早未确认的分片算起，与接收方一致
+            while let Some(begin) = next_begin {
+                let lowest = in_flight.keys().next().copied().unwrap_or(begin);
+                if begin >= lowest + window {
+                    break;
                 }
+                let length = Self::send_slice(tcpstream, header, &mut self.send_buf, self.checksum, msg, begin)?;
+                in_flight.insert(begin, 0);
+                let end = begin + length as u64;
+                next_begin = (end < whole_len).then_some(end);
+            }
+            if in_flight.is_empty() {
+                break;
+            }
+            // 等待任意一个分片的应答
+            let rhd = MessageHeader::read_from(tcpstream)?;
+            if !rhd.is_response() {
+                return Err(Error::Protocol("expected a response while sending"));
+            }
+            let Some(retries) = in_flight.get_mut(&rhd.begin) else {
+                return Err(Error::Protocol("response for a slice not in flight"));
+            };
+            if rhd.is_correct() {
+                in_flight.remove(&rhd.begin);
+            } else if *retries >= MAX_RETRANSMITS {
+                return Err(Error::Checksum { begin: rhd.begin, retries: *retries });
+            } else {
+                // 重传
+                *retries += 1;
+                Self::send_slice(tcpstream, header, &mut self.send_buf, self.checksum, msg, rhd.begin)?;
             }
         }
         Ok(())
     }
 
-    /// 发送一个Message
-    pub fn send_message(&mut self, msg: &impl Message) -> Result<(), &'static str> {
-        self.send_bytes(msg.as_bytes())
+    /// 发送从 `begin` 开始的一片，协议头与数据合并为一次写入，返回该片长度
+    fn send_slice(
+        tcpstream: &mut TcpStream,
+        header: &mut MessageHeader,
+        buf: &mut Vec<u8>,
+        checksum: Checksum,
+        msg: &[u8],
+        begin: u64,
+    ) -> io::Result<usize> {
+        // 填写偏移和长度
+        let begin = begin as usize;
+        let length = SLICE_SIZE.min(msg.len() - begin);
+        header.begin = begin as u64;
+        header.length = length as u32;
+        // 要发送的数据
+        let data = &msg[begin..begin + length];
+        // 填写该片数据的校验码
+        header.check = checksum.compute(data);
+        buf.clear();
+        buf.extend_from_slice(&header.encode());
+        buf.extend_from_slice(data);
+        tcpstream.write_all(buf).map(|_| length)
     }
-    
-    pub fn receive_bytes_buf<'a>(&mut self, buf: &'a mut Vec<u8>) -> Result<&'a mut Vec<u8>, Box<dyn std::error::Error>> {
+
+    /// 编码并发送一个 `Message`
+    pub fn send_message(&mut self, msg: &impl Message) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        msg.encode(&mut buf);
+        self.send_bytes(&buf)
+    }
+
+    /// 接收一条消息并解码为 `M`
+    pub fn recv_message<M: Message>(&mut self) -> Result<M, Error> {
+        M::decode(self.receive_bytes()?)
+    }
+
+    /// 接收一条消息到 `buf` 中
+    ///
+    /// 分片可以乱序到达，按偏移放回原位，收齐全部数据后返回
+    pub fn receive_bytes_buf<'a>(&mut self, buf: &'a mut Vec<u8>) -> Result<&'a mut Vec<u8>, Error> {
+        let tcpstream = connected(&mut self.tcpstream)?;
         let checked_data = buf;
         checked_data.clear();
-        if let Some(tcpstream) = &mut self.tcpstream {
-            // 是否有后续分片
-            let mut left_data = true;
-            while left_data {
-                // 读取该片协议头
-                tcpstream.read_exact(self.recv_hd.as_bytes_mut()).unwrap();
-                let header = &self.recv_hd;
-                // 读取数据
-                let mut buff = vec![0; header.length];
-                tcpstream.read_exact(&mut buff).map_err(|e| e.kind().to_string())?;
-                // 校验数据
-                let mut h = MessageHeader::default();
-                h.set_response();
-                h.begin = header.begin;
-                h.length = header.length;
-                if Self::default_checksum(&buff) == header.check {
-                    // 合并数据
-                    checked_data.append(&mut buff);
-                    // 发送确认包
-                    h.set_correct();
-                    tcpstream.write_all(h.as_bytes()).map_err(|_| "send response error")?;
-                } else {
-                    // 发送重传包
-                    tcpstream.write_all(h.as_bytes()).map_err(|_| "send response error")?;
-                    continue;
+        // 最早缺失的分片序号，以及在它之后已收到的分片序号
+        let mut contiguous = 0;
+        let mut received = BTreeSet::new();
+        let mut whole_length = None;
+        loop {
+            // 读取该片协议头
+            self.recv_hd = MessageHeader::read_from(tcpstream)?;
+            let header = &self.recv_hd;
+            if header.is_response() || header.is_negotiation() {
+                return Err(Error::Protocol("expected a data slice"));
+            }
+            if *whole_length.get_or_insert(header.whole_length) != header.whole_length {
+                return Err(Error::Protocol("message length changed between slices"));
+            }
+            // 分配缓冲区前检查分片的位置与长度，除最后一片外都是整片
+            let slice = SLICE_SIZE as u64;
+            let tail = header.whole_length - header.begin;
+            if !header.begin.is_multiple_of(slice) || header.length as u64 != tail.min(slice) {
+                return Err(Error::Protocol("slice is not aligned to SLICE_SIZE"));
+            }
+            let index = header.begin / slice;
+            if index >= contiguous + MAX_WINDOW as u64 {
+                return Err(Error::Protocol("slice beyond the receive window"));
+            }
+            // 读取数据
+            let mut buff = vec![0; header.length as usize];
+            tcpstream.read_exact(&mut buff)?;
+            // 校验数据
+            let mut h = MessageHeader::default();
+            h.set_response();
+            h.begin = header.begin;
+            h.length = header.length;
+            h.whole_length = header.whole_length;
+            if header.checksum == self.checksum.id() && self.checksum.compute(&buff) == header.check {
+                // 按偏移放入数据，缓冲区随实际收到的数据增长
+                let begin = header.begin as usize;
+                let end = begin + buff.len();
+                if checked_data.len() < end {
+                    checked_data.resize(end, 0);
                 }
-                // 计数后移
-                left_data = if header.begin + header.length == header.whole_length {
-                    false
-                } else {
-                    true
+                // 重复的分片只应答
+                if index >= contiguous && received.insert(index) {
+                    checked_data[begin..end].copy_from_slice(&buff);
+                    while received.remove(&contiguous) {
+                        contiguous += 1;
+                    }
                 }
+                // 发送确认包
+                h.set_correct();
+                tcpstream.write_all(&h.encode())?;
+            } else {
+                // 发送重传包
+                tcpstream.write_all(&h.encode())?;
+                continue;
+            }
+            // 空消息也有一片
+            if contiguous == header.whole_length.div_ceil(slice).max(1) {
+                break;
             }
         }
         Ok(checked_data)
     }
 
     /// 接收字节
-    pub fn receive_bytes(&mut self) -> Result<&mut Vec<u8>, Box<dyn std::error::Error>> {
-        let checked_data = &mut self.recv_buf;
-        checked_data.clear();
-        if let Some(tcpstream) = &mut self.tcpstream {
-            // 是否有后续分片
-            let mut left_data = true;
-            while left_data {
-                // 读取该片协议头
-                tcpstream.read_exact(self.recv_hd.as_bytes_mut()).unwrap();
-                let header = &self.recv_hd;
-                // 读取数据
-                let mut buff = vec![0; header.length];
-                tcpstream.read_exact(&mut buff).map_err(|e| e.kind().to_string())?;
-                // 校验数据
-                let mut h = MessageHeader::default();
-                h.set_response();
-                h.begin = header.begin;
-                h.length = header.length;
-                if Self::default_checksum(&buff) == header.check {
-                    // 合并数据
-                    checked_data.append(&mut buff);
-                    // 发送确认包
-                    h.set_correct();
-                    tcpstream.write_all(h.as_bytes()).map_err(|_| "send response error")?;
-                } else {
-                    // 发送重传包
-                    tcpstream.write_all(h.as_bytes()).map_err(|_| "send response error")?;
-                    continue;
-                }
-                // 计数后移
-                left_data = if header.begin + header.length == header.whole_length {
-                    false
-                } else {
-                    true
-                }
-            }
-        }
-        Ok(checked_data)
+    pub fn receive_bytes(&mut self) -> Result<&mut Vec<u8>, Error> {
+        let mut buf = std::mem::take(&mut self.recv_buf);
+        let result = self.receive_bytes_buf(&mut buf).map(|_| ());
+        self.recv_buf = buf;
+        result.map(|_| &mut self.recv_buf)
     }
 
 }
 
+/// 连接已被取走时返回错误
+fn connected(tcpstream: &mut Option<TcpStream>) -> Result<&mut TcpStream, Error> {
+    tcpstream.as_mut().ok_or_else(|| Error::Io(io::ErrorKind::NotConnected.into()))
+}
+
 /// 将一个Sized的引用转为字节引用
+///
+/// 结果依赖平台的字长、字节序与填充，不应作为网络上的编码
 pub fn sized_as_bytes<T>(t: &T) -> &[u8] {
     unsafe {
-        let p: *const u8 = std::mem::transmute(t);
+        let p = t as *const T as *const u8;
         std::slice::from_raw_parts(p, std::mem::size_of::<T>())
     }
 }
@@ -282,7 +466,7 @@ pub fn sized_as_bytes<T>(t: &T) -> &[u8] {
 /// 将一个Sized的可变引用转为字节可变引用
 pub fn sized_as_bytes_mut<T>(t: &mut T) -> &mut [u8] {
     unsafe {
-        let p: *mut u8 = std::mem::transmute(t);
+        let p = t as *mut T as *mut u8;
         std::slice::from_raw_parts_mut(p, std::mem::size_of::<T>())
     }
 }
@@ -292,52 +476,396 @@ mod tests {
     use super::*;
     use std::{thread, net::TcpListener};
 
+    /// 在随机端口上监听，返回监听器与地址，避免并行的测试争用同一端口
+    fn listen() -> (TcpListener, std::net::SocketAddr) {
+        let listen = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listen.local_addr().unwrap();
+        (listen, addr)
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let mut h = MessageHeader {
+            version: VERSION,
+            flag: 0,
+            checksum: 0x01,
+            reserved: 0x02,
+            begin: 0x0304_0506_0708_090a,
+            length: 0x0b0c_0d0e,
+            whole_length: 0x0f10_1112_1314_1516,
+            check: 0x1718_191a,
+        };
+        h.set_sliced();
+        // 固定的大端序布局，与平台无关
+        let expected: [u8; HEADER_LEN] = [
+            2, 0b10, 0x01, 0x02,
+            0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a,
+            0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+            0x17, 0x18, 0x19, 0x1a,
+        ];
+        assert_eq!(h.encode(), expected);
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        // 超过 32 位的偏移与长度在任何平台上都能还原
+        let cases = [
+            (0, 0, 0),
+            (0, 1024, 4096),
+            (u32::MAX as u64, u32::MAX, u32::MAX as u64 * 2),
+            (u64::MAX - 10, 10, u64::MAX),
+        ];
+        for (begin, length, whole_length) in cases {
+            let mut h = MessageHeader { begin, length, whole_length, check: 0xdead_beef, ..Default::default() };
+            h.set_response();
+            h.set_sliced();
+            h.set_correct();
+            let decoded = MessageHeader::decode(&h.encode()).unwrap();
+            assert_eq!(decoded, h);
+            assert!(decoded.is_response() && decoded.is_sliced() && decoded.is_correct());
+        }
+    }
+
+    #[test]
+    fn test_header_invalid() {
+        let mut buf = MessageHeader::default().encode();
+        buf[0] = 1;
+        assert_eq!(MessageHeader::decode(&buf), Err(HeaderError::UnsupportedVersion(1)));
+        let h = MessageHeader { begin: 10, length: 10, whole_length: 15, ..Default::default() };
+        assert!(matches!(MessageHeader::decode(&h.encode()), Err(HeaderError::OutOfRange { .. })));
+        let h = MessageHeader { begin: u64::MAX, length: 1, whole_length: u64::MAX, ..Default::default() };
+        assert!(matches!(MessageHeader::decode(&h.encode()), Err(HeaderError::OutOfRange { .. })));
+    }
+
     #[test]
     fn test_basic() {
-        const ADDR: &str = "127.0.0.1:31000";
-        let t1 = thread::spawn(|| {
-            let listen = TcpListener::bind(ADDR).unwrap();
+        let (listen, addr) = listen();
+        let s = String::from("hello world").repeat(1024);
+        let expected = s.clone();
+        let t1 = thread::spawn(move || {
             let (stream, _) = listen.accept().unwrap();
             let mut client = MessageCenter::new(stream);
-            let s = String::from("hello world").repeat(1024);
             client.send_bytes(s.as_bytes()).unwrap();
-            
+
             let data = client.receive_bytes().unwrap();
-            let data = String::from_utf8(data.to_vec()).unwrap();
-            println!("server recv: {}", data);
-        });  
+            String::from_utf8(data.to_vec()).unwrap()
+        });
 
-        let mut server = MessageCenter::connect(ADDR).unwrap();
+        let mut server = MessageCenter::connect(addr).unwrap();
         let data = server.receive_bytes().unwrap();
-        let data = String::from_utf8(data.to_vec()).unwrap();
-        println!("client recv: {}", data);
+        assert_eq!(String::from_utf8(data.to_vec()).unwrap(), expected);
 
         server.send_bytes(b"hello from client").unwrap();
 
-        t1.join().unwrap();
+        assert_eq!(t1.join().unwrap(), "hello from client");
     }
 
     #[test]
     fn test_twice() {
-        const ADDR: &str = "127.0.0.1:31000";
-        let t1 = thread::spawn(|| {
-            let listen = TcpListener::bind(ADDR).unwrap();
+        let (listen, addr) = listen();
+        let t1 = thread::spawn(move || {
             let (stream, _) = listen.accept().unwrap();
             let mut client = MessageCenter::new(stream);
             let s = String::from("hello world").repeat(1024);
             client.send_bytes(s.as_bytes()).unwrap();
             client.send_bytes(b"another").unwrap();
-        });  
+        });
 
         let mut buf = Vec::new();
-        let mut server = MessageCenter::connect(ADDR).unwrap();
+        let mut server = MessageCenter::connect(addr).unwrap();
         server.receive_bytes_buf(&mut buf).unwrap();
-        let data = String::from_utf8(buf.to_vec()).unwrap();
-        println!("client recv: {}", data);
+        assert_eq!(buf, "hello world".repeat(1024).as_bytes());
         server.receive_bytes_buf(&mut buf).unwrap();
-        let data = String::from_utf8(buf.to_vec()).unwrap();
-        println!("client recv: {}", data);
+        assert_eq!(buf, b"another");
+
+        t1.join().unwrap();
+    }
 
+    #[test]
+    fn test_negotiate() {
+        let cases = [
+            (Checksum::all(), Checksum::all(), Checksum::all()[0]),
+            (&[Checksum::Adler32, Checksum::Crc32][..], &[Checksum::Crc32][..], Checksum::Crc32),
+            (&[Checksum::Adler32][..], &[Checksum::Crc32][..], Checksum::None),
+            (&[][..], Checksum::all(), Checksum::None),
+        ];
+        for (local, remote, expected) in cases {
+            let (listen, addr) = listen();
+            let t1 = thread::spawn(move || {
+                let (stream, _) = listen.accept().unwrap();
+                let mut client = MessageCenter::new(stream);
+                assert_eq!(client.negotiate(remote).unwrap(), expected);
+                client.send_bytes(b"negotiated").unwrap();
+            });
+            let mut server = MessageCenter::connect(addr).unwrap();
+            assert_eq!(server.negotiate(local).unwrap(), expected);
+            assert_eq!(server.receive_bytes().unwrap(), b"negotiated");
+            t1.join().unwrap();
+        }
+    }
+
+    /// 经过一个代理发送 `msg`，代理把前 `flips` 个数据分片各翻转一位，返回接收到的数据
+    fn send_through_flipper(checksum: Checksum, msg: Vec<u8>, flips: usize) -> Vec<u8> {
+        let (proxy, proxy_addr) = listen();
+        let (listen, addr) = listen();
+        let t_proxy = thread::spawn(move || {
+            let (mut from, _) = proxy.accept().unwrap();
+            let mut to = TcpStream::connect(addr).unwrap();
+            let (mut back_from, mut back_to) = (to.try_clone().unwrap(), from.try_clone().unwrap());
+            let back = thread::spawn(move || io::copy(&mut back_from, &mut back_to));
+            let mut flipped = 0;
+            while let Ok(header) = MessageHeader::read_from(&mut from) {
+                let mut data = vec![0; header.length as usize];
+                from.read_exact(&mut data).unwrap();
+                if !header.is_negotiation() && flipped < flips {
+                    let i = flipped * 7 % data.len();
+                    data[i] ^= 1 << (flipped % 8);
+                    flipped += 1;
+                }
+                to.write_all(&header.encode()).unwrap();
+                to.write_all(&data).unwrap();
+            }
+            drop(to);
+            back.join().unwrap().unwrap();
+            flipped
+        });
+        let t_send = thread::spawn(move || {
+            let mut client = MessageCenter::connect(proxy_addr).unwrap();
+            client.negotiate(&[checksum]).unwrap();
+            client.send_bytes(&msg).unwrap();
+        });
+
+        let (stream, _) = listen.accept().unwrap();
+        let mut server = MessageCenter::new(stream);
+        assert_eq!(server.negotiate(&[checksum]).unwrap(), checksum);
+        let received = server.receive_bytes().unwrap().clone();
+        t_send.join().unwrap();
+        drop(server);
+        assert_eq!(t_proxy.join().unwrap(), flips);
+        received
+    }
+
+    #[test]
+    fn test_corrupted_slices_retransmitted() {
+        let msg: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 251) as u8).collect();
+        for &checksum in Checksum::all().iter().filter(|&&c| c != Checksum::None) {
+            // 同一分片连续损坏也会一直重传到正确为止
+            assert_eq!(send_through_flipper(checksum, msg.clone(), 3), msg, "{:?}", checksum);
+            assert_eq!(send_through_flipper(checksum, b"short".to_vec(), 1), b"short", "{:?}", checksum);
+        }
+        // 不校验时损坏的数据会被直接接收
+        assert_ne!(send_through_flipper(Checksum::None, msg.clone(), 1), msg);
+    }
+
+    #[test]
+    fn test_window() {
+        let msg: Vec<u8> = (0..100_000u32).map(|i| (i % 253) as u8).collect();
+        for window in [1, 3, DEFAULT_WINDOW, 200] {
+            let (listen, addr) = listen();
+            let expected = msg.clone();
+            let t1 = thread::spawn(move || {
+                let (stream, _) = listen.accept().unwrap();
+                let mut client = MessageCenter::new(stream);
+                client.window = window;
+                client.send_bytes(&expected).unwrap();
+                client.send_bytes(b"tail").unwrap();
+            });
+            let mut server = MessageCenter::connect(addr).unwrap();
+            assert_eq!(server.receive_bytes().unwrap(), &msg, "window {}", window);
+            assert_eq!(server.receive_bytes().unwrap(), b"tail");
+            t1.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let (listen, addr) = listen();
+        let msg: Vec<u8> = (0..SLICE_SIZE as u32 * 3 + 10).map(|i| i as u8).collect();
+        let expected = msg.clone();
+        let t1 = thread::spawn(move || {
+            let (mut stream, _) = listen.accept().unwrap();
+            // 倒序发送各片，并重复发送一片
+            let begins = [3, 2, 2, 1, 0].map(|i| (i * SLICE_SIZE) as u64);
+            let mut header = MessageHeader { whole_length: msg.len() as u64, ..Default::default() };
+            let mut buf = Vec::new();
+            for begin in begins {
+                MessageCenter::send_slice(&mut stream, &mut header, &mut buf, Checksum::None, &msg, begin).unwrap();
+            }
+            // 每片都得到确认
+            for begin in begins {
+                let rhd = MessageHeader::read_from(&mut stream).unwrap();
+                assert!(rhd.is_response() && rhd.is_correct());
+                assert_eq!(rhd.begin, begin);
+            }
+        });
+        let mut server = MessageCenter::connect(addr).unwrap();
+        assert_eq!(server.receive_bytes().unwrap(), &expected);
+        t1.join().unwrap();
+    }
+
+    #[test]
+    fn test_peer_closed() {
+        let (listen, addr) = listen();
+        let t1 = thread::spawn(move || drop(listen.accept().unwrap()));
+        let mut center = MessageCenter::connect(addr).unwrap();
+        t1.join().unwrap();
+        assert!(matches!(center.receive_bytes(), Err(Error::PeerClosed)));
+        assert!(matches!(center.send_bytes(b"lost"), Err(Error::PeerClosed)));
+        center.tcpstream = None;
+        assert!(matches!(center.send_bytes(b"lost"), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (listen, addr) = listen();
+        let mut center = MessageCenter::connect(addr).unwrap();
+        let _peer = listen.accept().unwrap();
+        center.set_timeout(Some(Duration::from_millis(50))).unwrap();
+        assert!(matches!(center.receive_bytes(), Err(Error::Timeout)));
+    }
+
+    /// 由 `peer` 在原始连接上应答，返回本端 `f` 的结果
+    fn against_raw_peer<T: Send + 'static>(
+        peer: impl FnOnce(TcpStream) + Send + 'static,
+        f: impl FnOnce(&mut MessageCenter) -> T,
+    ) -> T {
+        let (listen, addr) = listen();
+        let t1 = thread::spawn(move || peer(listen.accept().unwrap().0));
+        let mut center = MessageCenter::connect(addr).unwrap();
+        let result = f(&mut center);
+        drop(center);
+        t1.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        // 旧版本的协议头
+        let result = against_raw_peer(
+            |mut s| {
+                let mut buf = MessageHeader::default().encode();
+                buf[0] = 1;
+                s.write_all(&buf).unwrap();
+            },
+            |c| c.receive_bytes().map(|_| ()),
+        );
+        assert!(matches!(result, Err(Error::Version(1))));
+
+        // 接收时收到应答包
+        let result = against_raw_peer(
+            |mut s| {
+                let mut h = MessageHeader::default();
+                h.set_response();
+                s.write_all(&h.encode()).unwrap();
+            },
+            |c| c.receive_bytes().map(|_| ()),
+        );
+        assert!(matches!(result, Err(Error::Protocol(_))));
+
+        // 协商时收到数据包
+        let result = against_raw_peer(
+            |mut s| s.write_all(&MessageHeader::default().encode()).unwrap(),
+            |c| c.negotiate(Checksum::all()),
+        );
+        assert!(matches!(result, Err(Error::Protocol(_))));
+
+        // 对端总是要求重传
+        let result = against_raw_peer(
+            |mut s| {
+                while let Ok(h) = MessageHeader::read_from(&mut s) {
+                    let mut data = vec![0; h.length as usize];
+                    s.read_exact(&mut data).unwrap();
+                    let mut r = MessageHeader { begin: h.begin, length: h.length, whole_length: h.whole_length, ..Default::default() };
+                    r.set_response();
+                    if s.write_all(&r.encode()).is_err() {
+                        break;
+                    }
+                }
+            },
+            |c| c.send_bytes(b"never"),
+        );
+        assert!(matches!(result, Err(Error::Checksum { begin: 0, retries: MAX_RETRANSMITS })));
+    }
+
+    #[test]
+    fn test_hostile_negotiation() {
+        let result = against_raw_peer(
+            |mut s| {
+                let mut header = MessageHeader { length: u32::MAX, whole_length: u32::MAX as u64, ..Default::default() };
+                header.set_negotiation();
+                s.write_all(&header.encode()).unwrap();
+                let _ = io::copy(&mut s, &mut io::sink());
+            },
+            |c| c.negotiate(Checksum::all()),
+        );
+        assert!(matches!(result, Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_hostile_slices() {
+        let hostile = |h: MessageHeader| {
+            against_raw_peer(
+                move |mut s| {
+                    s.write_all(&h.encode()).unwrap();
+                    // 对端可能已断开，之后的数据写不进去也无妨
+                    let _ = s.write_all(&vec![0; h.length.min(4096) as usize]);
+                },
+                |c| c.receive_bytes().map(|v| v.len()),
+            )
+        };
+        let slice = SLICE_SIZE as u64;
+        let whole = 1 << 50;
+        // 超出接收窗口的巨大偏移
+        let result = hostile(MessageHeader { begin: 1 << 40, length: 0, whole_length: whole, ..Default::default() });
+        assert!(matches!(result, Err(Error::Protocol(_))));
+        // 过长的分片
+        let result = hostile(MessageHeader { begin: 0, length: u32::MAX, whole_length: whole, ..Default::default() });
+        assert!(matches!(result, Err(Error::Protocol(_))));
+        // 未对齐、不完整的分片
+        let result = hostile(MessageHeader { begin: 10, length: 10, whole_length: 3 * slice, ..Default::default() });
+        assert!(matches!(result, Err(Error::Protocol(_))));
+        let result = hostile(MessageHeader { begin: 0, length: 10, whole_length: 3 * slice, ..Default::default() });
+        assert!(matches!(result, Err(Error::Protocol(_))));
+        // 窗口内的最后一片本身是合法的，但后面缺失的分片不会到来
+        let result = hostile(MessageHeader { begin: 2 * slice, length: 1, whole_length: 2 * slice + 1, ..Default::default() });
+        assert!(matches!(result, Err(Error::PeerClosed)));
+    }
+
+    #[test]
+    fn test_message() {
+        crate::message! {
+            #[derive(Debug, PartialEq, Clone)]
+            enum Request {
+                Get { key: String },
+                Put(String, Vec<u8>),
+                Quit,
+            }
+        }
+
+        let (listen, addr) = listen();
+        let requests = vec![
+            Request::Put("big".into(), vec![9; 5000]),
+            Request::Get { key: "big".into() },
+            Request::Quit,
+        ];
+        let sent = requests.clone();
+        let t1 = thread::spawn(move || {
+            let (stream, _) = listen.accept().unwrap();
+            let mut client = MessageCenter::new(stream);
+            for r in &sent {
+                client.send_message(r).unwrap();
+            }
+            client.send_message(&()).unwrap();
+            client.send_message(&Some(3u8)).unwrap();
+        });
+        let mut server = MessageCenter::connect(addr).unwrap();
+        for r in requests {
+            assert_eq!(server.recv_message::<Request>().unwrap(), r);
+        }
+        // 空消息同样需要一次收发
+        server.recv_message::<()>().unwrap();
+        assert!(matches!(server.recv_message::<u32>(), Err(Error::Protocol(_))));
         t1.join().unwrap();
     }
 }
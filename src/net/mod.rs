@@ -1,3 +1,10 @@
-// 自己写的玩具停等协议，很烂
-// #[deprecated]
-// pub mod message;
+mod error;
+
+pub use error::Error;
+
+/// 消息分片的校验算法
+pub mod checksum;
+/// 消息的编码与解码
+pub mod codec;
+/// 滑动窗口消息协议，协议头使用固定宽度的大端序编码
+pub mod message;
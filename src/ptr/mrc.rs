@@ -15,7 +15,7 @@ impl<T: ?Sized> Deref for Pointer<T> {
 impl<T: ?Sized> Drop for Pointer<T> {
     #[inline]
     fn drop(&mut self) {
-        unsafe { Box::from_raw(self.0) };
+        unsafe { drop(Box::from_raw(self.0)) };
     }
 }
 
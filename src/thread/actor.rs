@@ -0,0 +1,496 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::channel::oneshot;
+use super::{lock, Job, PoolError, TaskHandle, ThreadPool};
+
+/// 一次调度最多处理的消息数，之后让出工作线程给其他 actor
+const BATCH: usize = 32;
+
+/// Actor，在线程池中逐条处理邮箱中的消息
+///
+/// 同一个 actor 的消息不会并发处理，因此状态不需要加锁
+pub trait Actor: Send + 'static {
+    type Message: Send + 'static;
+
+    /// 处理一条消息，panic 时由工厂重新创建 actor，该消息丢失
+    ///
+    /// 工厂或 `started` panic 时同样算作一次重启，触发创建的消息丢失
+    fn handle(&mut self, msg: Self::Message);
+
+    /// 创建或重启后、处理第一条消息前调用
+    fn started(&mut self) {}
+
+    /// 调用 `ActorRef::stop` 并处理完剩余消息后调用，其中的 panic 会被忽略
+    fn stopped(&mut self) {}
+}
+
+/// actor 已停止，消息被退回
+pub struct MailboxClosed<M>(pub M);
+
+impl<M> fmt::Debug for MailboxClosed<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MailboxClosed(..)")
+    }
+}
+
+impl<M> fmt::Display for MailboxClosed<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("actor mailbox is closed")
+    }
+}
+
+impl<M> std::error::Error for MailboxClosed<M> {}
+
+/// `ask` 的回复通道，随消息一起发给 actor
+pub struct Reply<R> {
+    tx          : oneshot::Sender<Result<R, String>>,
+}
+
+impl<R> Reply<R> {
+    /// 回复请求方，请求方已放弃等待时忽略
+    pub fn send(self, r: R) {
+        let _ = self.tx.send(Ok(r));
+    }
+}
+
+/// 擦除 actor 类型后的邮箱
+trait Mailbox<M>: Send + Sync {
+    fn post(self: Arc<Self>, msg: M) -> Result<(), MailboxClosed<M>>;
+    fn stop(self: Arc<Self>);
+    fn restarts(&self) -> usize;
+}
+
+struct Queue<M> {
+    msgs        : VecDeque<M>,
+    /// 已在线程池中排队或正在处理
+    scheduled   : bool,
+    stopped     : bool,
+    /// `stopped` 钩子已调用，actor 已释放
+    finished    : bool,
+}
+
+struct ActorCell<A: Actor> {
+    queue       : Mutex<Queue<A::Message>>,
+    actor       : Mutex<Option<A>>,
+    factory     : Box<dyn Fn() -> A + Send + Sync>,
+    pool        : Weak<ThreadPool>,
+    restarts    : AtomicUsize,
+    /// 最多重启的次数，超过后 actor 停止
+    max_restarts: usize,
+}
+
+impl<A: Actor> ActorCell<A> {
+    /// 提交一次批处理，调用前须在 `queue` 锁内设置 `scheduled` 并释放锁
+    ///
+    /// 不按线程池的拒绝策略等待或在当前线程执行，队列已满时把批处理退回给调用方；
+    /// 线程池已关闭时返回 `Err(None)`
+    fn schedule(self: &Arc<Self>) -> Result<(), Option<Job>> {
+        let Some(pool) = self.pool.upgrade() else {
+            return Err(None);
+        };
+        let cell = Arc::clone(self);
+        match pool.try_execute(move || cell.run()) {
+            Ok(()) => Ok(()),
+            Err(PoolError::Rejected(job)) => Err(Some(job)),
+            Err(PoolError::Shutdown) => Err(None),
+        }
+    }
+
+    fn spawn_actor(&self) -> A {
+        let mut actor = (self.factory)();
+        actor.started();
+        actor
+    }
+
+    /// 记录一次失败，超过重启上限时停止 actor 并丢弃剩余消息
+    fn restart(&self) {
+        if self.restarts.load(Ordering::SeqCst) < self.max_restarts {
+            self.restarts.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+        let mut queue = lock(&self.queue);
+        queue.msgs.clear();
+        queue.stopped = true;
+    }
+
+    /// 所有用户代码都在 `catch_unwind` 中调用，保证每次都能重置 `scheduled`
+    ///
+    /// 线程池队列已满时在当前线程中继续处理下一批
+    fn run(self: Arc<Self>) {
+        loop {
+            let mut actor = lock(&self.actor);
+            for _ in 0..BATCH {
+                let Some(msg) = lock(&self.queue).msgs.pop_front() else { break };
+                let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                    actor.get_or_insert_with(|| self.spawn_actor()).handle(msg)
+                }));
+                if handled.is_err() {
+                    // 丢弃可能已损坏的状态，下一条消息前重新创建
+                    *actor = None;
+                    self.restart();
+                }
+            }
+
+            let mut queue = lock(&self.queue);
+            if !queue.msgs.is_empty() {
+                // 不持锁提交，`scheduled` 保持为 `true`
+                drop(queue);
+                drop(actor);
+                match self.schedule() {
+                    Ok(()) => return,
+                    Err(Some(_)) => continue,
+                    Err(None) => {},
+                }
+                actor = lock(&self.actor);
+                queue = lock(&self.queue);
+                // 线程池已关闭，剩余消息无法处理
+                queue.msgs.clear();
+                queue.stopped = true;
+            }
+            queue.scheduled = false;
+            if queue.stopped && !queue.finished {
+                queue.finished = true;
+                drop(queue);
+                if let Some(mut a) = actor.take() {
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| a.stopped()));
+                }
+            }
+            return;
+        }
+    }
+}
+
+impl<A: Actor> Mailbox<A::Message> for ActorCell<A> {
+    fn post(self: Arc<Self>, msg: A::Message) -> Result<(), MailboxClosed<A::Message>> {
+        let mut queue = lock(&self.queue);
+        if queue.stopped {
+            return Err(MailboxClosed(msg));
+        }
+        queue.msgs.push_back(msg);
+        if queue.scheduled {
+            return Ok(());
+        }
+        queue.scheduled = true;
+        drop(queue);
+        match self.schedule() {
+            Ok(()) => {},
+            // 线程池队列已满，在当前线程中处理
+            Err(Some(run)) => run(),
+            Err(None) => {
+                let mut queue = lock(&self.queue);
+                queue.scheduled = false;
+                queue.stopped = true;
+                if let Some(msg) = queue.msgs.pop_back() {
+                    return Err(MailboxClosed(msg));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn stop(self: Arc<Self>) {
+        let mut queue = lock(&self.queue);
+        if queue.stopped {
+            return;
+        }
+        queue.stopped = true;
+        if queue.scheduled {
+            return;
+        }
+        // 空闲时需要一次调度来调用 `stopped`
+        queue.scheduled = true;
+        drop(queue);
+        match self.schedule() {
+            Ok(()) => {},
+            Err(Some(run)) => run(),
+            Err(None) => {
+                let mut queue = lock(&self.queue);
+                queue.scheduled = false;
+                queue.finished = true;
+            },
+        }
+    }
+
+    fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::SeqCst)
+    }
+}
+
+/// actor 的引用，可克隆后在多个线程中发送消息
+///
+/// 所有引用都被释放后，actor 在处理完已有消息后随邮箱一起释放
+pub struct ActorRef<M> {
+    mailbox     : Arc<dyn Mailbox<M>>,
+}
+
+impl<M> Clone for ActorRef<M> {
+    fn clone(&self) -> Self {
+        ActorRef { mailbox: Arc::clone(&self.mailbox) }
+    }
+}
+
+impl<M: Send + 'static> ActorRef<M> {
+    /// 在线程池中创建 actor，`factory` 用于首次创建以及 panic 后重启
+    ///
+    /// 多个 actor 共享线程池的工作线程，actor 只有在有消息时才占用工作线程
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::Arc;
+    /// use ptstd::thread::{Actor, ActorRef, Reply, ThreadPool};
+    ///
+    /// enum Msg { Add(u64), Get(Reply<u64>) }
+    ///
+    /// struct Counter(u64);
+    ///
+    /// impl Actor for Counter {
+    ///     type Message = Msg;
+    ///     fn handle(&mut self, msg: Msg) {
+    ///         match msg {
+    ///             Msg::Add(n) => self.0 += n,
+    ///             Msg::Get(reply) => reply.send(self.0),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let pool = Arc::new(ThreadPool::new(2));
+    /// let counter = ActorRef::spawn(&pool, || Counter(0));
+    /// counter.send(Msg::Add(2)).unwrap();
+    /// assert_eq!(counter.ask(Msg::Get).join().unwrap(), 2);
+    /// ```
+    pub fn spawn<A, F>(pool: &Arc<ThreadPool>, factory: F) -> ActorRef<M>
+    where
+        A: Actor<Message = M>,
+        F: Fn() -> A + Send + Sync + 'static,
+    {
+        Self::spawn_supervised(pool, usize::MAX, factory)
+    }
+
+    /// 同 `spawn`，但最多重启 `max_restarts` 次，再次失败时 actor 停止，
+    /// 剩余消息被丢弃，之后的消息被退回
+    pub fn spawn_supervised<A, F>(pool: &Arc<ThreadPool>, max_restarts: usize, factory: F) -> ActorRef<M>
+    where
+        A: Actor<Message = M>,
+        F: Fn() -> A + Send + Sync + 'static,
+    {
+        let cell = Arc::new(ActorCell {
+            queue: Mutex::new(Queue {
+                msgs: VecDeque::new(),
+                scheduled: false,
+                stopped: false,
+                finished: false,
+            }),
+            actor: Mutex::new(None),
+            factory: Box::new(factory),
+            pool: Arc::downgrade(pool),
+            restarts: AtomicUsize::new(0),
+            max_restarts,
+        });
+        ActorRef { mailbox: cell }
+    }
+
+    /// 发送一条消息，actor 已停止或线程池已关闭时退回消息
+    pub fn send(&self, msg: M) -> Result<(), MailboxClosed<M>> {
+        Arc::clone(&self.mailbox).post(msg)
+    }
+
+    /// 发送一条带回复通道的消息，通过返回的句柄等待回复
+    ///
+    /// actor 没有回复就丢弃了消息（例如处理时 panic 或已停止）时得到 `JoinError::Canceled`
+    pub fn ask<R, F>(&self, f: F) -> TaskHandle<R>
+    where
+        F: FnOnce(Reply<R>) -> M,
+    {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.send(f(Reply { tx }));
+        TaskHandle::new(rx)
+    }
+
+    /// 停止接收新消息，已有消息处理完后调用 `Actor::stopped`
+    pub fn stop(&self) {
+        Arc::clone(&self.mailbox).stop();
+    }
+
+    /// actor 因 panic 被重启的次数，包括工厂或 `started` panic
+    pub fn restarts(&self) -> usize {
+        self.mailbox.restarts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread::JoinError;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use crate::thread::RejectionPolicy;
+
+    enum Msg {
+        Add(u64),
+        Get(Reply<u64>),
+        Boom,
+        /// 通知开始处理后阻塞到 `gate` 被关闭
+        Wait(mpsc::Sender<()>, mpsc::Receiver<()>),
+    }
+
+    struct Counter {
+        n           : u64,
+        stopped     : Arc<AtomicBool>,
+    }
+
+    impl Actor for Counter {
+        type Message = Msg;
+
+        fn handle(&mut self, msg: Msg) {
+            match msg {
+                Msg::Add(n) => self.n += n,
+                Msg::Get(reply) => reply.send(self.n),
+                Msg::Boom => panic!("boom"),
+                Msg::Wait(started, gate) => {
+                    started.send(()).unwrap();
+                    let _ = gate.recv();
+                },
+            }
+        }
+
+        fn stopped(&mut self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn counter(pool: &Arc<ThreadPool>) -> (ActorRef<Msg>, Arc<AtomicBool>) {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let s = Arc::clone(&stopped);
+        let actor = ActorRef::spawn(pool, move || Counter { n: 0, stopped: Arc::clone(&s) });
+        (actor, stopped)
+    }
+
+    #[test]
+    fn test_many_actors() {
+        let pool = Arc::new(ThreadPool::new(2));
+        let actors: Vec<_> = (0..100).map(|_| counter(&pool).0).collect();
+        for i in 0..1000u64 {
+            actors[(i % 100) as usize].send(Msg::Add(i)).unwrap();
+        }
+        let total: u64 = actors.iter().map(|a| a.ask(Msg::Get).join().unwrap()).sum();
+        assert_eq!(total, 499500);
+    }
+
+    #[test]
+    fn test_restart() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let (actor, _) = counter(&pool);
+        actor.send(Msg::Add(5)).unwrap();
+        actor.send(Msg::Boom).unwrap();
+        actor.send(Msg::Add(1)).unwrap();
+        // 重启后状态由工厂重新创建
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 1);
+        assert_eq!(actor.restarts(), 1);
+        assert_eq!(pool.stats().panicked, 0);
+    }
+
+    #[test]
+    fn test_factory_panic() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let created = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&created);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let actor = ActorRef::spawn(&pool, move || {
+            if c.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("factory");
+            }
+            Counter { n: 0, stopped: Arc::clone(&stopped) }
+        });
+        // 触发创建的消息随失败的创建一起丢失
+        actor.send(Msg::Add(1)).unwrap();
+        actor.send(Msg::Add(2)).unwrap();
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 2);
+        assert_eq!(actor.restarts(), 1);
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+
+        // 工厂总是失败时，超过重启上限后停止
+        let actor = ActorRef::spawn_supervised(&pool, 2, || -> Counter { panic!("factory") });
+        for i in 0..5 {
+            let _ = actor.send(Msg::Add(i));
+        }
+        assert!(matches!(actor.ask(Msg::Get).join(), Err(JoinError::Canceled)));
+        assert!(matches!(actor.send(Msg::Add(1)), Err(MailboxClosed(_))));
+        assert_eq!(actor.restarts(), 2);
+        assert_eq!(pool.stats().panicked, 0);
+    }
+
+    #[test]
+    fn test_stop() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let (actor, stopped) = counter(&pool);
+        actor.send(Msg::Add(1)).unwrap();
+        let pending = actor.ask(Msg::Get);
+        actor.stop();
+        assert_eq!(pending.join().unwrap(), 1);
+        assert!(matches!(actor.send(Msg::Add(1)), Err(MailboxClosed(Msg::Add(1)))));
+        assert!(matches!(actor.ask(Msg::Get).join(), Err(JoinError::Canceled)));
+        pool.shutdown();
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    /// 只有一个工作线程、队列容量为 1 的线程池，返回时工作线程被占用且队列已满，
+    /// 丢弃返回的发送端后恢复
+    fn full_pool(policy: RejectionPolicy) -> (Arc<ThreadPool>, mpsc::Sender<()>) {
+        let pool = Arc::new(ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .rejection_policy(policy)
+            .build());
+        let (started, wait) = mpsc::channel();
+        let (gate, rx) = mpsc::channel::<()>();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = rx.recv();
+        }).unwrap();
+        wait.recv().unwrap();
+        pool.execute(|| {}).unwrap();
+        (pool, gate)
+    }
+
+    #[test]
+    fn test_full_pool_caller_runs() {
+        let (pool, gate) = full_pool(RejectionPolicy::CallerRuns);
+        let (actor, _) = counter(&pool);
+        // 线程池队列已满时在发送方线程中处理，不能在持有邮箱锁时重入
+        for i in 0..100u64 {
+            actor.send(Msg::Add(i)).unwrap();
+        }
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 4950);
+        drop(gate);
+        actor.send(Msg::Add(50)).unwrap();
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_full_pool_block() {
+        let (pool, gate) = full_pool(RejectionPolicy::Block);
+        let (actor, _) = counter(&pool);
+        // 发送方不会持有邮箱锁等待队列空位
+        for i in 0..100u64 {
+            actor.send(Msg::Add(i)).unwrap();
+        }
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 4950);
+        drop(gate);
+
+        // 工作线程中的 actor 重新调度时队列已满，在当前线程中继续处理而不是等待自己
+        let (started, wait) = mpsc::channel();
+        let (gate, rx) = mpsc::channel();
+        actor.send(Msg::Wait(started, rx)).unwrap();
+        wait.recv().unwrap();
+        for _ in 0..100 {
+            actor.send(Msg::Add(1)).unwrap();
+        }
+        pool.execute(|| {}).unwrap();
+        drop(gate);
+        assert_eq!(actor.ask(Msg::Get).join().unwrap(), 5050);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::{RejectionPolicy, Scheduler, ThreadPool, Trace};
+
+/// 工作线程启动或退出时调用的钩子，参数为工作线程编号
+pub(crate) type WorkerHook = dyn Fn(usize) + Send + Sync + 'static;
+
+/// 线程池构建器
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{Scheduler, ThreadPool};
+///
+/// let pool = ThreadPool::builder()
+///     .num_workers(4)
+///     .scheduler(Scheduler::WorkStealing)
+///     .thread_name("compute")
+///     .stack_size(4 << 20)
+///     .build();
+/// let h = pool.submit(|| 1 + 1);
+/// assert_eq!(h.join().unwrap(), 2);
+/// ```
+#[derive(Clone)]
+pub struct ThreadPoolBuilder {
+    pub(crate) core_size    : usize,
+    pub(crate) max_size     : usize,
+    pub(crate) keep_alive   : Duration,
+    pub(crate) scheduler    : Scheduler,
+    pub(crate) capacity     : Option<usize>,
+    pub(crate) rejection    : RejectionPolicy,
+    pub(crate) aging        : Option<Duration>,
+    pub(crate) name_prefix  : String,
+    pub(crate) stack_size   : Option<usize>,
+    pub(crate) on_start     : Option<Arc<WorkerHook>>,
+    pub(crate) on_stop      : Option<Arc<WorkerHook>>,
+    pub(crate) affinity     : Vec<usize>,
+    pub(crate) deterministic: Option<Trace>,
+}
+
+impl fmt::Debug for ThreadPoolBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPoolBuilder")
+            .field("core_size", &self.core_size)
+            .field("max_size", &self.max_size)
+            .field("keep_alive", &self.keep_alive)
+            .field("scheduler", &self.scheduler)
+            .field("capacity", &self.capacity)
+            .field("rejection", &self.rejection)
+            .field("aging", &self.aging)
+            .field("name_prefix", &self.name_prefix)
+            .field("stack_size", &self.stack_size)
+            .field("affinity", &self.affinity)
+            .field("deterministic", &self.deterministic)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// 默认核心线程数与最大线程数都为可用的并行度，
+    /// 空闲线程保留 60 秒，调度方式为 `Scheduler::Global`，队列无界且不开启老化，
+    /// 线程名为 `ptstd-worker-{编号}`
+    pub fn new() -> ThreadPoolBuilder {
+        let n = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        ThreadPoolBuilder {
+            core_size: n,
+            max_size: n,
+            keep_alive: Duration::from_secs(60),
+            scheduler: Scheduler::default(),
+            capacity: None,
+            rejection: RejectionPolicy::default(),
+            aging: None,
+            name_prefix: "ptstd-worker".to_string(),
+            stack_size: None,
+            on_start: None,
+            on_stop: None,
+            affinity: Vec::new(),
+            deterministic: None,
+        }
+    }
+
+    /// 固定的工作线程数，同时设置核心线程数与最大线程数
+    pub fn num_workers(mut self, n: usize) -> Self {
+        self.core_size = n;
+        self.max_size = n;
+        self
+    }
+
+    /// 核心线程数，空闲时也不会退出
+    pub fn core_size(mut self, n: usize) -> Self {
+        self.core_size = n;
+        self
+    }
+
+    /// 最大线程数，任务积压时扩容的上限
+    pub fn max_size(mut self, n: usize) -> Self {
+        self.max_size = n;
+        self
+    }
+
+    /// 超过核心线程数的线程空闲多久后退出
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// 开启优先级老化，任务每等待 `interval` 有效优先级提升 1，避免低优先级任务饿死
+    pub fn aging(mut self, interval: Duration) -> Self {
+        self.aging = Some(interval);
+        self
+    }
+
+    /// 调度方式
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// 任务队列容量，默认不限制
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// 队列已满时的处理策略，默认阻塞提交方
+    pub fn rejection_policy(mut self, policy: RejectionPolicy) -> Self {
+        self.rejection = policy;
+        self
+    }
+
+    /// 工作线程名的前缀，线程名为 `{prefix}-{编号}`
+    pub fn thread_name(mut self, prefix: impl Into<String>) -> Self {
+        self.name_prefix = prefix.into();
+        self
+    }
+
+    /// 工作线程的栈大小，单位为字节，默认使用标准库的设置
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// 工作线程启动后、取任务前在该线程中调用，参数为工作线程编号
+    ///
+    /// 钩子中的 panic 会被忽略
+    pub fn on_thread_start<F>(mut self, f: F) -> Self where F: Fn(usize) + Send + Sync + 'static {
+        self.on_start = Some(Arc::new(f));
+        self
+    }
+
+    /// 工作线程退出前在该线程中调用，包括缩容退出与因 panic 被替换的情况
+    ///
+    /// 钩子中的 panic 会被忽略
+    pub fn on_thread_stop<F>(mut self, f: F) -> Self where F: Fn(usize) + Send + Sync + 'static {
+        self.on_stop = Some(Arc::new(f));
+        self
+    }
+
+    /// 把编号为 `i` 的工作线程绑定到 `cpus[i % cpus.len()]` 上，仅在 Linux 上生效，
+    /// 绑定失败时忽略
+    pub fn cpu_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.affinity = cpus.into_iter().collect();
+        self
+    }
+
+    /// 确定性模式，用于复现并发问题的测试
+    ///
+    /// 不创建工作线程，任务只在调用 `ThreadPool::run_one` 或 `run_until_idle` 时
+    /// 于调用方线程中逐个执行，每次由以 `seed` 初始化的随机数从待执行的任务中选一个。
+    /// 相同的种子与相同的提交顺序得到相同的执行顺序，执行记录通过 `ThreadPool::trace` 取得。
+    /// 此模式下队列容量、调度方式、优先级与线程相关的设置都不生效
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.deterministic = Some(Trace::new(seed, Vec::new()));
+        self
+    }
+
+    /// 确定性模式，先按 `trace` 中记录的顺序执行，之后由其中的种子决定顺序
+    ///
+    /// 重放到的任务不在待执行列表中时 `run_one` 会 panic
+    pub fn replay(mut self, trace: Trace) -> Self {
+        self.deterministic = Some(trace);
+        self
+    }
+
+    /// 创建线程池，最大线程数为 0 或核心线程数超过最大线程数时 panic
+    pub fn build(self) -> ThreadPool {
+        ThreadPool::with_builder(self)
+    }
+}
@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use super::{lock, Job};
+
+/// 排队中的可取消任务，取消时由令牌取出丢弃，开始执行时由工作线程取出
+pub(crate) struct Tracked {
+    pub(crate) job      : Option<Job>,
+    /// 取消时在丢弃任务后调用，用于把任务从线程池的队列中移除
+    pub(crate) on_cancel: Option<Job>,
+}
+
+pub(crate) type JobSlot = Arc<Mutex<Tracked>>;
+
+struct State {
+    children    : Vec<Weak<Node>>,
+    pending     : Vec<Weak<Mutex<Tracked>>>,
+}
+
+struct Node {
+    cancelled   : AtomicBool,
+    state       : Mutex<State>,
+    cond        : Condvar,
+    /// 保持父节点存活，使中间层令牌被释放后取消仍能传递到子令牌
+    _parent     : Option<Arc<Node>>,
+}
+
+/// 协作式取消令牌
+///
+/// 克隆得到的令牌共享同一状态；子令牌随父令牌一起取消，取消子令牌不影响父令牌。
+/// 任务通过 `is_cancelled` 轮询或 `wait` 阻塞等待取消
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{CancellationToken, ThreadPool};
+///
+/// let pool = ThreadPool::new(2);
+/// let token = CancellationToken::new();
+/// let h = pool.submit({
+///     let token = token.child_token();
+///     move || {
+///         let mut n = 0;
+///         while !token.is_cancelled() {
+///             n += 1;
+///             std::thread::yield_now();
+///         }
+///         n
+///     }
+/// });
+/// token.cancel();
+/// assert!(h.join().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct CancellationToken {
+    node        : Arc<Node>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancellationToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+impl CancellationToken {
+    /// 创建一个未取消的令牌
+    pub fn new() -> CancellationToken {
+        Self::with_parent(None)
+    }
+
+    fn with_parent(parent: Option<Arc<Node>>) -> CancellationToken {
+        CancellationToken {
+            node: Arc::new(Node {
+                cancelled: AtomicBool::new(false),
+                state: Mutex::new(State { children: Vec::new(), pending: Vec::new() }),
+                cond: Condvar::new(),
+                _parent: parent,
+            }),
+        }
+    }
+
+    /// 创建子令牌，父令牌已取消时子令牌也处于取消状态
+    pub fn child_token(&self) -> CancellationToken {
+        let child = Self::with_parent(Some(Arc::clone(&self.node)));
+        let mut state = lock(&self.node.state);
+        if self.is_cancelled() {
+            drop(state);
+            child.cancel();
+        } else {
+            state.children.retain(|c| c.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.node));
+        }
+        child
+    }
+
+    /// 取消令牌及其所有子令牌，唤醒等待方并丢弃尚未开始的任务
+    pub fn cancel(&self) {
+        let mut state = lock(&self.node.state);
+        if self.node.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let children = std::mem::take(&mut state.children);
+        let pending = std::mem::take(&mut state.pending);
+        drop(state);
+        self.node.cond.notify_all();
+
+        for slot in pending.iter().filter_map(Weak::upgrade) {
+            let (job, on_cancel) = {
+                let mut tracked = lock(&slot);
+                (tracked.job.take(), tracked.on_cancel.take())
+            };
+            // 已开始执行的任务不再调用 `on_cancel`
+            if job.is_some() {
+                drop(job);
+                if let Some(on_cancel) = on_cancel {
+                    on_cancel();
+                }
+            }
+        }
+        for node in children.into_iter().filter_map(|c| c.upgrade()) {
+            CancellationToken { node }.cancel();
+        }
+    }
+
+    /// 是否已取消
+    pub fn is_cancelled(&self) -> bool {
+        self.node.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 阻塞直到令牌被取消
+    pub fn wait(&self) {
+        let mut state = lock(&self.node.state);
+        while !self.is_cancelled() {
+            state = self.node.cond.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// 最多等待 `timeout`，返回令牌是否已被取消
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = lock(&self.node.state);
+        while !self.is_cancelled() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self.node.cond
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+
+    /// 登记一个尚未开始的任务，令牌已取消时返回 `None`
+    pub(crate) fn track(&self, job: Job, on_cancel: Job) -> Option<JobSlot> {
+        let mut state = lock(&self.node.state);
+        if self.is_cancelled() {
+            return None;
+        }
+        let slot = Arc::new(Mutex::new(Tracked { job: Some(job), on_cancel: Some(on_cancel) }));
+        state.pending.retain(|s| s.strong_count() > 0);
+        state.pending.push(Arc::downgrade(&slot));
+        Some(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_hierarchy() {
+        let root = CancellationToken::new();
+        let child = root.child_token();
+        let grandchild = child.child_token();
+        let other = root.child_token();
+        // 中间层被释放后仍能传递取消
+        let orphan = root.child_token().child_token();
+
+        child.cancel();
+        assert!(child.is_cancelled() && grandchild.is_cancelled());
+        assert!(!root.is_cancelled() && !other.is_cancelled());
+
+        root.cancel();
+        assert!(other.is_cancelled() && orphan.is_cancelled());
+        assert!(root.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn test_wait() {
+        let token = CancellationToken::new();
+        assert!(!token.wait_timeout(Duration::from_millis(10)));
+        let t = token.clone();
+        let h = thread::spawn(move || t.wait());
+        thread::sleep(Duration::from_millis(20));
+        token.cancel();
+        h.join().unwrap();
+        assert!(token.wait_timeout(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_track() {
+        let token = CancellationToken::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let slot = token.track(Box::new(|| ()), Box::new(move || tx.send(()).unwrap())).unwrap();
+        token.cancel();
+        assert!(lock(&slot).job.is_none());
+        rx.try_recv().unwrap();
+        assert!(token.track(Box::new(|| ()), Box::new(|| ())).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+struct Slot<T> {
+    /// 等于 `2 * pos` 时可写入位置 `pos`，等于 `2 * pos + 1` 时可读出位置 `pos`。
+    /// 乘 2 使容量为 1 时写入后的标记与下一圈的可写标记也不会相同
+    stamp       : AtomicUsize,
+    value       : UnsafeCell<MaybeUninit<T>>,
+}
+
+/// 有界无锁队列（Dmitry Vyukov 的 MPMC 环形队列）
+///
+/// `head`、`tail` 只增不减，槽位为 `pos % capacity`，64 位下计数实际不会回绕
+pub(crate) struct ArrayQueue<T> {
+    slots       : Box<[Slot<T>]>,
+    head        : AtomicUsize,
+    tail        : AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    pub(crate) fn new(capacity: usize) -> ArrayQueue<T> {
+        assert!(capacity > 0, "capacity should not be zero");
+        let slots = (0..capacity)
+            .map(|i| Slot { stamp: AtomicUsize::new(2 * i), value: UnsafeCell::new(MaybeUninit::uninit()) })
+            .collect();
+        ArrayQueue { slots, head: AtomicUsize::new(0), tail: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// 放入数据，队列已满时原样返回
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            match stamp.wrapping_sub(2 * pos) as isize {
+                0 => match self.tail.compare_exchange_weak(pos, pos + 1, Ordering::SeqCst, Ordering::Relaxed) {
+                    Ok(_) => {
+                        // SAFETY: 抢到 `pos` 后只有本线程能写这个槽位，读方要等到 stamp 更新
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.stamp.store(2 * pos + 1, Ordering::Release);
+                        return Ok(());
+                    },
+                    Err(actual) => pos = actual,
+                },
+                // 槽位中还是上一圈的数据，队列已满
+                d if d < 0 => return Err(value),
+                // 其他线程已抢先写入，重新读取
+                _ => {
+                    thread::yield_now();
+                    pos = self.tail.load(Ordering::Relaxed);
+                },
+            }
+        }
+    }
+
+    /// 取出数据，队列为空时返回 `None`
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            match stamp.wrapping_sub(2 * pos + 1) as isize {
+                0 => match self.head.compare_exchange_weak(pos, pos + 1, Ordering::SeqCst, Ordering::Relaxed) {
+                    Ok(_) => {
+                        // SAFETY: stamp 表明写入已完成，抢到 `pos` 后只有本线程能读这个槽位
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.stamp.store(2 * (pos + self.slots.len()), Ordering::Release);
+                        return Some(value);
+                    },
+                    Err(actual) => pos = actual,
+                },
+                // 槽位还未写入，队列为空
+                d if d < 0 => return None,
+                _ => {
+                    thread::yield_now();
+                    pos = self.head.load(Ordering::Relaxed);
+                },
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            // 两次读取之间 tail 未变时结果一致
+            if self.tail.load(Ordering::SeqCst) == tail {
+                return tail.saturating_sub(head).min(self.slots.len());
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
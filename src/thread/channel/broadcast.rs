@@ -0,0 +1,306 @@
+//! 有界广播通道，每条数据被所有接收端各收到一次
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::thread::lock;
+
+use super::error::SendError;
+
+/// 广播通道接收时的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum RecvError {
+    /// 接收端落后太多，最早的若干条数据已被覆盖，下次从仍保留的最早数据开始接收
+    #[error("receiver lagged behind by {0} messages")]
+    Lagged(u64),
+    /// 所有发送端都已丢弃且没有未读数据
+    #[error("receiving on a closed channel")]
+    Closed,
+}
+
+/// 广播通道非阻塞接收时的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// 暂时没有新数据
+    #[error("receiving on an empty channel")]
+    Empty,
+    /// 见 `RecvError::Lagged`
+    #[error("receiver lagged behind by {0} messages")]
+    Lagged(u64),
+    /// 所有发送端都已丢弃且没有未读数据
+    #[error("receiving on a closed channel")]
+    Closed,
+}
+
+/// 广播通道等待超时时的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    /// 等待超时
+    #[error("timed out waiting on receive operation")]
+    Timeout,
+    /// 见 `RecvError::Lagged`
+    #[error("receiver lagged behind by {0} messages")]
+    Lagged(u64),
+    /// 所有发送端都已丢弃且没有未读数据
+    #[error("receiving on a closed channel")]
+    Closed,
+}
+
+struct State<T> {
+    /// 最近的至多 `capacity` 条数据
+    buffer      : VecDeque<T>,
+    /// `buffer[0]` 的序号
+    head        : u64,
+    senders     : usize,
+    receivers   : usize,
+}
+
+impl<T> State<T> {
+    /// 下一条数据的序号
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    /// 取出序号为 `*next` 的数据并前移
+    fn take(&self, next: &mut u64) -> Result<T, TryRecvError> where T: Clone {
+        if *next < self.head {
+            let lagged = self.head - *next;
+            *next = self.head;
+            return Err(TryRecvError::Lagged(lagged));
+        }
+        if *next < self.tail() {
+            let value = self.buffer[(*next - self.head) as usize].clone();
+            *next += 1;
+            return Ok(value);
+        }
+        if self.senders == 0 {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+struct Shared<T> {
+    state       : Mutex<State<T>>,
+    capacity    : usize,
+    sent        : Condvar,
+}
+
+/// 创建保留最近 `capacity` 条数据的广播通道，容量为 0 时 panic
+///
+/// 发送永不阻塞，已满时覆盖最早的数据，落后的接收端会收到 `Lagged`
+///
+/// # Example
+/// ```
+/// use ptstd::thread::channel::broadcast;
+///
+/// let (tx, mut rx1) = broadcast::channel(16);
+/// let mut rx2 = tx.subscribe();
+/// tx.send(1).unwrap();
+/// assert_eq!(rx1.recv(), Ok(1));
+/// assert_eq!(rx2.recv(), Ok(1));
+/// ```
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    if capacity == 0 {
+        panic!("capacity should not be zero");
+    }
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State { buffer: VecDeque::with_capacity(capacity), head: 0, senders: 1, receivers: 1 }),
+        capacity,
+        sent: Condvar::new(),
+    });
+    (Sender { shared: Arc::clone(&shared) }, Receiver { shared, next: 0 })
+}
+
+/// 广播通道的发送端，可以克隆
+pub struct Sender<T> {
+    shared      : Arc<Shared<T>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// 发送数据，返回当前的接收端数量，没有接收端时退回数据
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = lock(&self.shared.state);
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == self.shared.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        let receivers = state.receivers;
+        drop(state);
+        self.shared.sent.notify_all();
+        Ok(receivers)
+    }
+
+    /// 创建新的接收端，只接收此后发送的数据
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = lock(&self.shared.state);
+        state.receivers += 1;
+        Receiver { shared: Arc::clone(&self.shared), next: state.tail() }
+    }
+
+    /// 当前的接收端数量
+    pub fn receiver_count(&self) -> usize {
+        lock(&self.shared.state).receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        lock(&self.shared.state).senders += 1;
+        Sender { shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.shared.state);
+        state.senders -= 1;
+        let last = state.senders == 0;
+        drop(state);
+        if last {
+            self.shared.sent.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").field("capacity", &self.shared.capacity).finish_non_exhaustive()
+    }
+}
+
+/// 广播通道的接收端，克隆得到的接收端从相同的位置继续接收
+pub struct Receiver<T> {
+    shared      : Arc<Shared<T>>,
+    /// 下一条要接收的数据序号
+    next        : u64,
+}
+
+impl<T: Clone> Receiver<T> {
+    /// 不阻塞地接收
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        lock(&self.shared.state).take(&mut self.next)
+    }
+
+    /// 阻塞直到有新数据或所有发送端都已丢弃
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        match self.recv_until(None) {
+            Ok(v) => Ok(v),
+            Err(RecvTimeoutError::Lagged(n)) => Err(RecvError::Lagged(n)),
+            Err(_) => Err(RecvError::Closed),
+        }
+    }
+
+    /// 最多等待 `timeout`
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let shared = &self.shared;
+        let mut state = lock(&shared.state);
+        loop {
+            match state.take(&mut self.next) {
+                Ok(v) => return Ok(v),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvTimeoutError::Lagged(n)),
+                Err(TryRecvError::Closed) => return Err(RecvTimeoutError::Closed),
+                Err(TryRecvError::Empty) => {},
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    shared.sent.wait_timeout(state, deadline - now).unwrap_or_else(PoisonError::into_inner).0
+                },
+                None => shared.sent.wait(state).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        lock(&self.shared.state).receivers += 1;
+        Receiver { shared: Arc::clone(&self.shared), next: self.next }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        lock(&self.shared.state).receivers -= 1;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").field("next", &self.next).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_broadcast() {
+        let (tx, rx) = channel(64);
+        let receivers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Ok(v) = rx.recv() {
+                        got.push(v);
+                    }
+                    got
+                })
+            })
+            .collect();
+        drop(rx);
+        for i in 0..50 {
+            assert_eq!(tx.send(i), Ok(4));
+        }
+        drop(tx);
+        for t in receivers {
+            assert_eq!(t.join().unwrap(), (0..50).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_lagged() {
+        let (tx, mut rx) = channel(2);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.recv(), Ok(4));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+        // 新订阅的接收端只收到此后的数据
+        let mut late = tx.subscribe();
+        tx.send(5).unwrap();
+        assert_eq!(late.recv(), Ok(5));
+        drop(tx);
+        assert_eq!(late.recv(), Err(RecvError::Closed));
+        drop((rx, late));
+    }
+
+    #[test]
+    fn test_no_receivers() {
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+use thiserror::Error;
+
+/// 所有接收端都已丢弃，数据被退回
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a disconnected channel")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+/// `try_send` 的错误，数据被退回
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// 通道已满
+    Full(T),
+    /// 所有接收端都已丢弃
+    Disconnected(T),
+}
+
+impl<T> TrySendError<T> {
+    /// 取回未发送的数据
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(v) | TrySendError::Disconnected(v) => v,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a disconnected channel"),
+        }
+    }
+}
+
+impl<T> std::error::Error for TrySendError<T> {}
+
+/// `send_timeout` 的错误，数据被退回
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T> {
+    /// 等待超时，通道仍是满的
+    Timeout(T),
+    /// 所有接收端都已丢弃
+    Disconnected(T),
+}
+
+impl<T> SendTimeoutError<T> {
+    /// 取回未发送的数据
+    pub fn into_inner(self) -> T {
+        match self {
+            SendTimeoutError::Timeout(v) | SendTimeoutError::Disconnected(v) => v,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            SendTimeoutError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("timed out waiting on send operation"),
+            SendTimeoutError::Disconnected(_) => f.write_str("sending on a disconnected channel"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendTimeoutError<T> {}
+
+/// 通道已空且所有发送端都已丢弃
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+#[error("receiving on a disconnected channel")]
+pub struct RecvError;
+
+/// `try_recv` 的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// 暂时没有数据
+    #[error("receiving on an empty channel")]
+    Empty,
+    /// 通道已空且所有发送端都已丢弃
+    #[error("receiving on a disconnected channel")]
+    Disconnected,
+}
+
+/// `recv_timeout` 的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    /// 等待超时
+    #[error("timed out waiting on receive operation")]
+    Timeout,
+    /// 通道已空且所有发送端都已丢弃
+    #[error("receiving on a disconnected channel")]
+    Disconnected,
+}
@@ -0,0 +1,73 @@
+//! 线程间通道
+//!
+//! - `bounded`、`unbounded`：多生产者多消费者通道，有界通道基于无锁环形队列
+//! - `broadcast`：有界广播通道，每条数据被所有接收端各收到一次
+//! - `oneshot`：一次性通道
+//! - `Select`、`select!`：同时等待多个接收端
+
+mod array;
+pub mod broadcast;
+mod error;
+mod mpmc;
+pub mod oneshot;
+mod select;
+mod waiter;
+pub use error::{RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError};
+pub use mpmc::{bounded, unbounded, IntoIter, Iter, Receiver, Sender, TryIter};
+pub use select::{Select, Selectable};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_select() {
+        let (tx1, rx1) = bounded::<u32>(4);
+        let (tx2, rx2) = unbounded::<u32>();
+        let producers = [(tx1, 0), (tx2, 100)].map(|(tx, base)| {
+            thread::spawn(move || {
+                for i in 0..50 {
+                    tx.send(base + i).unwrap();
+                }
+            })
+        });
+        let (mut from1, mut from2) = (Vec::new(), Vec::new());
+        let (mut open1, mut open2) = (true, true);
+        while open1 || open2 {
+            crate::select! {
+                recv(rx1) -> v => match v {
+                    Ok(v) => from1.push(v),
+                    Err(_) => open1 = false,
+                },
+                recv(rx2) -> v => match v {
+                    Ok(v) => from2.push(v),
+                    Err(_) => open2 = false,
+                },
+            }
+        }
+        for t in producers {
+            t.join().unwrap();
+        }
+        assert_eq!(from1, (0..50).collect::<Vec<_>>());
+        assert_eq!(from2, (100..150).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_select_timeout() {
+        let (_tx, rx) = unbounded::<i32>();
+        let (once_tx, once_rx) = oneshot::channel();
+        let mut sel = Select::new();
+        sel.recv(&rx);
+        let i = sel.recv(&once_rx);
+        assert_eq!(sel.select_timeout(Duration::from_millis(10)), None);
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            once_tx.send(7).unwrap();
+        });
+        assert_eq!(sel.select(), i);
+        assert_eq!(once_rx.try_select(), Some(Ok(7)));
+        t.join().unwrap();
+    }
+}
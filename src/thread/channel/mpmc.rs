@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::thread::lock;
+
+use super::array::ArrayQueue;
+use super::error::{RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError};
+use super::select::Selectable;
+use super::waiter::{Watch, Waiters};
+
+/// 数据的存放方式
+enum Flavor<T> {
+    /// 有界，无锁环形队列
+    Array(ArrayQueue<T>),
+    /// 无界，加锁的双端队列，临界区只有一次入队或出队
+    List(Mutex<VecDeque<T>>),
+}
+
+impl<T> Flavor<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        match self {
+            Flavor::Array(q) => q.push(value),
+            Flavor::List(q) => {
+                lock(q).push_back(value);
+                Ok(())
+            },
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        match self {
+            Flavor::Array(q) => q.pop(),
+            Flavor::List(q) => lock(q).pop_front(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Flavor::Array(q) => q.len(),
+            Flavor::List(q) => lock(q).len(),
+        }
+    }
+}
+
+struct Chan<T> {
+    queue       : Flavor<T>,
+    senders     : AtomicUsize,
+    receivers   : AtomicUsize,
+    /// 等待数据的接收方
+    recv_wait   : Waiters,
+    /// 等待空位的发送方
+    send_wait   : Waiters,
+}
+
+/// 创建容量为 `capacity` 的有界通道，通道满时发送方阻塞，容量为 0 时 panic
+///
+/// # Example
+/// ```
+/// use std::thread;
+/// use ptstd::thread::channel;
+///
+/// let (tx, rx) = channel::bounded(4);
+/// let consumers: Vec<_> = (0..2)
+///     .map(|_| {
+///         let rx = rx.clone();
+///         thread::spawn(move || rx.iter().sum::<u32>())
+///     })
+///     .collect();
+/// drop(rx);
+/// for i in 1..=100 {
+///     tx.send(i).unwrap();
+/// }
+/// drop(tx);
+/// let total: u32 = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+/// assert_eq!(total, 5050);
+/// ```
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    if capacity == 0 {
+        panic!("capacity should not be zero");
+    }
+    new(Flavor::Array(ArrayQueue::new(capacity)))
+}
+
+/// 创建无界通道，发送永不阻塞
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    new(Flavor::List(Mutex::new(VecDeque::new())))
+}
+
+fn new<T>(queue: Flavor<T>) -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan {
+        queue,
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        recv_wait: Waiters::new(),
+        send_wait: Waiters::new(),
+    });
+    (Sender { chan: Arc::clone(&chan) }, Receiver { chan })
+}
+
+/// 多生产者多消费者通道的发送端，可以克隆
+pub struct Sender<T> {
+    chan        : Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 不阻塞地发送
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.chan.receivers.load(Ordering::SeqCst) == 0 {
+            return Err(TrySendError::Disconnected(value));
+        }
+        match self.chan.queue.push(value) {
+            Ok(()) => {
+                self.chan.recv_wait.notify_one();
+                Ok(())
+            },
+            Err(value) => Err(TrySendError::Full(value)),
+        }
+    }
+
+    /// 发送，通道满时阻塞直到有空位
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match self.send_until(value, None) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(SendError(e.into_inner())),
+        }
+    }
+
+    /// 发送，通道满时最多等待 `timeout`
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_until(value, Some(Instant::now() + timeout))
+    }
+
+    fn send_until(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let mut value = Some(value);
+        let sent = self.chan.send_wait.wait_until(deadline, || {
+            let v = value.take()?;
+            match self.try_send(v) {
+                Ok(()) => Some(Ok(())),
+                Err(TrySendError::Full(v)) => {
+                    value = Some(v);
+                    None
+                },
+                Err(TrySendError::Disconnected(v)) => Some(Err(SendTimeoutError::Disconnected(v))),
+            }
+        });
+        match (sent, value) {
+            (Some(r), _) => r,
+            (None, Some(v)) => Err(SendTimeoutError::Timeout(v)),
+            (None, None) => unreachable!("value was sent"),
+        }
+    }
+
+    /// 通道中的数据数
+    pub fn len(&self) -> usize {
+        self.chan.queue.len()
+    }
+
+    /// 通道是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 通道容量，无界通道返回 `None`
+    pub fn capacity(&self) -> Option<usize> {
+        self.chan.capacity()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.senders.fetch_add(1, Ordering::SeqCst);
+        Sender { chan: Arc::clone(&self.chan) }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.chan.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.chan.recv_wait.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// 多生产者多消费者通道的接收端，可以克隆，每条数据只被一个接收端取走
+pub struct Receiver<T> {
+    chan        : Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// 不阻塞地接收
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(v) = self.chan.queue.pop() {
+            self.chan.send_wait.notify_one();
+            return Ok(v);
+        }
+        if self.chan.senders.load(Ordering::SeqCst) > 0 {
+            return Err(TryRecvError::Empty);
+        }
+        // 最后一个发送端退出前发送的数据
+        self.chan.queue.pop().ok_or(TryRecvError::Disconnected)
+    }
+
+    /// 接收，通道为空时阻塞直到有数据或所有发送端都已丢弃
+    pub fn recv(&self) -> Result<T, RecvError> {
+        match self.recv_until(None) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(RecvError),
+        }
+    }
+
+    /// 接收，通道为空时最多等待 `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        self.chan
+            .recv_wait
+            .wait_until(deadline, || match self.try_recv() {
+                Ok(v) => Some(Ok(v)),
+                Err(TryRecvError::Disconnected) => Some(Err(RecvTimeoutError::Disconnected)),
+                Err(TryRecvError::Empty) => None,
+            })
+            .unwrap_or(Err(RecvTimeoutError::Timeout))
+    }
+
+    /// 阻塞迭代，所有发送端都已丢弃且通道为空时结束
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// 不阻塞地取出当前所有数据
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+
+    /// 通道中的数据数
+    pub fn len(&self) -> usize {
+        self.chan.queue.len()
+    }
+
+    /// 通道是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 通道容量，无界通道返回 `None`
+    pub fn capacity(&self) -> Option<usize> {
+        self.chan.capacity()
+    }
+}
+
+impl<T> Chan<T> {
+    fn capacity(&self) -> Option<usize> {
+        match &self.queue {
+            Flavor::Array(q) => Some(q.capacity()),
+            Flavor::List(_) => None,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.chan.receivers.fetch_add(1, Ordering::SeqCst);
+        Receiver { chan: Arc::clone(&self.chan) }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.chan.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.chan.send_wait.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Watch for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        self.chan.queue.len() > 0 || self.chan.senders.load(Ordering::SeqCst) == 0
+    }
+
+    fn waiters(&self) -> &Waiters {
+        &self.chan.recv_wait
+    }
+}
+
+impl<T> Selectable for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn try_select(&self) -> Option<Self::Output> {
+        match self.try_recv() {
+            Ok(v) => Some(Ok(v)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+}
+
+/// 阻塞迭代器，见 `Receiver::iter`
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    rx          : &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+/// 非阻塞迭代器，见 `Receiver::try_iter`
+#[derive(Debug)]
+pub struct TryIter<'a, T> {
+    rx          : &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// 持有接收端的阻塞迭代器
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    rx          : Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::thread;
+
+    #[test]
+    fn test_mpmc() {
+        for (tx, rx) in [bounded(8), unbounded()] {
+            let producers: Vec<_> = (0..4u64)
+                .map(|p| {
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        for i in 0..1000 {
+                            tx.send(p * 1000 + i).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            drop(tx);
+            let consumers: Vec<_> = (0..4)
+                .map(|_| {
+                    let rx = rx.clone();
+                    thread::spawn(move || rx.iter().collect::<Vec<_>>())
+                })
+                .collect();
+            drop(rx);
+            for t in producers {
+                t.join().unwrap();
+            }
+            let mut seen = HashSet::new();
+            for t in consumers {
+                for v in t.join().unwrap() {
+                    // 每条数据只被取走一次
+                    assert!(seen.insert(v));
+                }
+            }
+            assert_eq!(seen.len(), 4000);
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let (tx, rx) = bounded(2);
+        assert_eq!(tx.capacity(), Some(2));
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(tx.send_timeout(3, Duration::from_millis(10)), Err(SendTimeoutError::Timeout(3)));
+        assert_eq!(rx.len(), 2);
+        let t = thread::spawn(move || {
+            // 阻塞直到接收方取走数据
+            tx.send(3).unwrap();
+            tx.send(4).unwrap();
+        });
+        assert_eq!(rx.iter().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (tx, rx) = bounded::<i32>(1);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+        tx.send(1).unwrap();
+        drop(tx);
+        // 断开后仍能取出剩余数据
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = unbounded();
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+
+        // 阻塞中的发送方在接收端丢弃后返回
+        let (tx, rx) = bounded(1);
+        tx.send(0).unwrap();
+        let t = thread::spawn(move || tx.send(1));
+        thread::sleep(Duration::from_millis(20));
+        drop(rx);
+        assert_eq!(t.join().unwrap(), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,187 @@
+//! 一次性通道，只能发送一条数据
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::thread::lock;
+
+use super::error::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use super::select::Selectable;
+use super::waiter::{Watch, Waiters};
+
+struct State<T> {
+    value       : Option<T>,
+    /// 发送端已发送或已丢弃
+    tx_gone     : bool,
+    /// 接收端已丢弃
+    rx_gone     : bool,
+}
+
+struct Inner<T> {
+    state       : Mutex<State<T>>,
+    waiters     : Waiters,
+}
+
+/// 创建一次性通道
+///
+/// # Example
+/// ```
+/// use std::thread;
+/// use ptstd::thread::channel::oneshot;
+///
+/// let (tx, rx) = oneshot::channel();
+/// thread::spawn(move || tx.send(42).unwrap());
+/// assert_eq!(rx.recv(), Ok(42));
+/// ```
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        state: Mutex::new(State { value: None, tx_gone: false, rx_gone: false }),
+        waiters: Waiters::new(),
+    });
+    (Sender { inner: Arc::clone(&inner) }, Receiver { inner })
+}
+
+/// 一次性通道的发送端
+pub struct Sender<T> {
+    inner       : Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 发送数据，接收端已丢弃时退回
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let mut state = lock(&self.inner.state);
+        if state.rx_gone {
+            return Err(SendError(value));
+        }
+        state.value = Some(value);
+        drop(state);
+        // 随后的 drop 标记发送端退出并唤醒接收方
+        Ok(())
+    }
+
+    /// 接收端是否已丢弃
+    pub fn is_closed(&self) -> bool {
+        lock(&self.inner.state).rx_gone
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        lock(&self.inner.state).tx_gone = true;
+        self.inner.waiters.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// 一次性通道的接收端
+pub struct Receiver<T> {
+    inner       : Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// 不阻塞地接收，数据已被取走后返回 `TryRecvError::Disconnected`
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = lock(&self.inner.state);
+        match state.value.take() {
+            Some(v) => Ok(v),
+            None if state.tx_gone => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// 阻塞直到收到数据或发送端丢弃
+    pub fn recv(self) -> Result<T, RecvError> {
+        match self.recv_until(None) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(RecvError),
+        }
+    }
+
+    /// 最多等待 `timeout`，超时后仍可继续接收
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        self.inner
+            .waiters
+            .wait_until(deadline, || match self.try_recv() {
+                Ok(v) => Some(Ok(v)),
+                Err(TryRecvError::Disconnected) => Some(Err(RecvTimeoutError::Disconnected)),
+                Err(TryRecvError::Empty) => None,
+            })
+            .unwrap_or(Err(RecvTimeoutError::Timeout))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.inner.state);
+        state.rx_gone = true;
+        // 数据不再有人接收，立即释放
+        let value = state.value.take();
+        drop(state);
+        drop(value);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Watch for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        let state = lock(&self.inner.state);
+        state.value.is_some() || state.tx_gone
+    }
+
+    fn waiters(&self) -> &Waiters {
+        &self.inner.waiters
+    }
+}
+
+impl<T> Selectable for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn try_select(&self) -> Option<Self::Output> {
+        match self.try_recv() {
+            Ok(v) => Some(Ok(v)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_oneshot() {
+        let (tx, rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+        let t = thread::spawn(move || tx.send("done").unwrap());
+        assert_eq!(rx.recv(), Ok("done"));
+        t.join().unwrap();
+
+        let (tx, rx) = channel::<i32>();
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel();
+        assert!(!tx.is_closed());
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,173 @@
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::waiter::{Signal, Watch};
+
+thread_local! {
+    /// 新建的 `Select` 开始检查的位置，每次加一，
+    /// 使每次调用 `select!` 都新建 `Select` 时也不会总是选中第一个接收端
+    static NEXT_START: Cell<usize> = const { Cell::new(0) };
+}
+
+/// 可以参与 `Select` 的接收端，目前为 `Receiver` 与 `oneshot::Receiver`
+pub trait Selectable: Watch {
+    /// 接收结果
+    type Output;
+
+    /// 不阻塞地接收，数据已被其他接收端取走时返回 `None`
+    fn try_select(&self) -> Option<Self::Output>;
+}
+
+/// 同时等待多个接收端，返回其中一个就绪的序号
+///
+/// 就绪指有数据可取或已断开。就绪后数据仍可能被同一通道的其他接收端取走，
+/// 因此调用方在 `try_select` 失败时应重新等待，`select!` 已处理这种情况
+///
+/// # Example
+/// ```
+/// use ptstd::thread::channel::{self, Select, Selectable};
+///
+/// let (tx1, rx1) = channel::unbounded::<i32>();
+/// let (_tx2, rx2) = channel::unbounded::<i32>();
+/// tx1.send(1).unwrap();
+/// let mut sel = Select::new();
+/// let i1 = sel.recv(&rx1);
+/// sel.recv(&rx2);
+/// assert_eq!(sel.select(), i1);
+/// assert_eq!(rx1.try_select(), Some(Ok(1)));
+/// ```
+pub struct Select<'a> {
+    handles     : Vec<&'a dyn Watch>,
+    /// 下次开始检查的位置，轮流检查避免后面的接收端饿死
+    start       : usize,
+}
+
+impl<'a> Select<'a> {
+    /// 创建空的选择器
+    pub fn new() -> Select<'a> {
+        let start = NEXT_START.with(|next| next.replace(next.get().wrapping_add(1)));
+        Select { handles: Vec::new(), start }
+    }
+
+    /// 添加接收端，返回其序号
+    pub fn recv<S: Selectable>(&mut self, rx: &'a S) -> usize {
+        self.handles.push(rx);
+        self.handles.len() - 1
+    }
+
+    /// 不阻塞地返回一个就绪的序号
+    pub fn try_select(&mut self) -> Option<usize> {
+        let len = self.handles.len();
+        if len == 0 {
+            return None;
+        }
+        let ready = (0..len)
+            .map(|i| (self.start + i) % len)
+            .find(|&i| self.handles[i].is_ready());
+        if let Some(i) = ready {
+            self.start = (i + 1) % len;
+        }
+        ready
+    }
+
+    /// 阻塞直到有接收端就绪，没有添加接收端时 panic
+    pub fn select(&mut self) -> usize {
+        self.wait(None).expect("no deadline")
+    }
+
+    /// 最多等待 `timeout`
+    pub fn select_timeout(&mut self, timeout: Duration) -> Option<usize> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    fn wait(&mut self, deadline: Option<Instant>) -> Option<usize> {
+        if self.handles.is_empty() {
+            panic!("no receiver to select");
+        }
+        loop {
+            if let Some(i) = self.try_select() {
+                return Some(i);
+            }
+            let signal = Signal::new();
+            for h in &self.handles {
+                h.waiters().register(&signal);
+            }
+            let ready = self.try_select();
+            let woken = ready.is_some() || signal.wait(deadline);
+            // 唤醒本次等待的通知会转交给该通道的其他等待方，避免被本次选择吞掉
+            for h in &self.handles {
+                h.waiters().cancel(&signal);
+            }
+            if ready.is_some() {
+                return ready;
+            }
+            if !woken {
+                return self.try_select();
+            }
+        }
+    }
+}
+
+impl Default for Select<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Select<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Select").field("len", &self.handles.len()).finish_non_exhaustive()
+    }
+}
+
+/// 等待多个接收端中的任意一个收到数据，执行对应的分支
+///
+/// 每个分支的形式为 `recv(rx) -> res => body`，`res` 为 `Result<T, RecvError>`，
+/// 通道断开时立即以 `Err(RecvError)` 选中。接收端表达式会被多次求值，应传入变量
+///
+/// # Example
+/// ```
+/// use std::thread;
+/// use ptstd::select;
+/// use ptstd::thread::channel::{self, oneshot};
+///
+/// let (tx1, rx1) = channel::unbounded();
+/// let (tx2, rx2) = oneshot::channel();
+/// thread::spawn(move || tx1.send(1).unwrap());
+/// thread::spawn(move || tx2.send("done").unwrap());
+/// let (mut num, mut msg) = (None, None);
+/// while num.is_none() || msg.is_none() {
+///     select! {
+///         recv(rx1) -> v => if let Ok(v) = v { num = Some(v) },
+///         recv(rx2) -> v => if let Ok(v) = v { msg = Some(v) },
+///     }
+/// }
+/// assert_eq!((num, msg), (Some(1), Some("done")));
+/// ```
+#[macro_export]
+macro_rules! select {
+    ($(recv($rx:expr) -> $res:pat => $body:expr),+ $(,)?) => {{
+        let mut sel = $crate::thread::channel::Select::new();
+        $( sel.recv(&$rx); )+
+        'select: loop {
+            let index = sel.select();
+            #[allow(unused_assignments)]
+            let mut i = 0usize;
+            $(
+                if index == i {
+                    match $crate::thread::channel::Selectable::try_select(&$rx) {
+                        Some(result) => {
+                            let $res = result;
+                            break 'select $body;
+                        },
+                        // 数据已被其他接收端取走
+                        None => continue 'select,
+                    }
+                }
+                i += 1;
+            )+
+            unreachable!();
+        }
+    }};
+}
@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+use crate::thread::lock;
+
+/// 阻塞中的线程，`select` 时同一个信号会登记在多个通道上
+pub struct Signal {
+    thread      : Thread,
+    notified    : AtomicBool,
+}
+
+impl Signal {
+    pub(crate) fn new() -> Arc<Signal> {
+        Arc::new(Signal { thread: thread::current(), notified: AtomicBool::new(false) })
+    }
+
+    /// 标记为已通知并唤醒线程，已被通知过时返回 `false`
+    fn notify(&self) -> bool {
+        if self.notified.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.thread.unpark();
+        true
+    }
+
+    fn is_notified(&self) -> bool {
+        self.notified.load(Ordering::SeqCst)
+    }
+
+    /// 等待通知，超过 `deadline` 仍未被通知时返回 `false`
+    pub(crate) fn wait(&self, deadline: Option<Instant>) -> bool {
+        while !self.is_notified() {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return self.is_notified();
+                    }
+                    thread::park_timeout(deadline - now);
+                },
+                None => thread::park(),
+            }
+        }
+        true
+    }
+}
+
+/// 通道一端的等待队列
+///
+/// 通知时按登记顺序唤醒，没有等待方时只读一次原子计数，不加锁
+pub struct Waiters {
+    list        : Mutex<VecDeque<Arc<Signal>>>,
+    /// 登记中的等待方数量
+    len         : AtomicUsize,
+}
+
+impl Waiters {
+    pub(crate) fn new() -> Waiters {
+        Waiters { list: Mutex::new(VecDeque::new()), len: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn register(&self, signal: &Arc<Signal>) {
+        let mut list = lock(&self.list);
+        list.push_back(Arc::clone(signal));
+        self.len.store(list.len(), Ordering::SeqCst);
+        drop(list);
+        // 与通知方的屏障配对：登记后再检查条件，通知方改变条件后再检查登记数
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// 撤销登记。信号已被本队列的通知消耗而调用方不再需要时，
+    /// 把通知转交给下一个等待方，避免唤醒丢失
+    pub(crate) fn cancel(&self, signal: &Arc<Signal>) {
+        let mut list = lock(&self.list);
+        let found = list.iter().position(|s| Arc::ptr_eq(s, signal));
+        if let Some(i) = found {
+            list.remove(i);
+            self.len.store(list.len(), Ordering::SeqCst);
+        } else if signal.is_notified() {
+            Self::notify_first(&mut list);
+            self.len.store(list.len(), Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn notify_one(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.len.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut list = lock(&self.list);
+        Self::notify_first(&mut list);
+        self.len.store(list.len(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn notify_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.len.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut list = lock(&self.list);
+        for signal in list.drain(..) {
+            signal.notify();
+        }
+        self.len.store(0, Ordering::SeqCst);
+    }
+
+    /// 唤醒第一个尚未被其他通道唤醒的等待方
+    fn notify_first(list: &mut VecDeque<Arc<Signal>>) {
+        while let Some(signal) = list.pop_front() {
+            if signal.notify() {
+                break;
+            }
+        }
+    }
+
+    /// 反复尝试 `f` 直到返回 `Some`，未就绪时登记并休眠，超过 `deadline` 返回 `None`
+    pub(crate) fn wait_until<R>(&self, deadline: Option<Instant>, mut f: impl FnMut() -> Option<R>) -> Option<R> {
+        loop {
+            if let Some(r) = f() {
+                return Some(r);
+            }
+            let signal = Signal::new();
+            self.register(&signal);
+            // 登记后再试一次，避免在登记前错过通知
+            if let Some(r) = f() {
+                self.cancel(&signal);
+                return Some(r);
+            }
+            if !signal.wait(deadline) {
+                self.cancel(&signal);
+                return f();
+            }
+        }
+    }
+}
+
+/// 可以被 `Select` 等待的接收端
+pub trait Watch {
+    /// 有数据可取或已断开
+    fn is_ready(&self) -> bool;
+    /// 接收方的等待队列
+    fn waiters(&self) -> &Waiters;
+}
@@ -0,0 +1,343 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::queue::{JobQueue, Pop};
+use super::{lock, Job};
+
+thread_local! {
+    /// 当前线程是否正在执行确定性模式下的任务
+    static IN_JOB: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 在调用方线程中执行确定性模式下的任务，执行期间 `in_job` 返回 `true`
+pub(crate) fn run_in_job<F: FnOnce()>(f: F) {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_JOB.with(|c| c.set(self.0));
+        }
+    }
+    let _reset = Reset(IN_JOB.with(|c| c.replace(true)));
+    f();
+}
+
+/// 当前线程是否正在执行确定性模式下的任务
+pub(crate) fn in_job() -> bool {
+    IN_JOB.with(Cell::get)
+}
+
+/// 确定性调度的执行记录
+///
+/// 任务按提交顺序从 0 开始编号，`order` 为实际执行的编号序列。
+/// 以 `{seed}:{id},{id},...` 的形式打印与解析，便于从失败的测试输出中复制后重放
+///
+/// # Example
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use ptstd::thread::{ThreadPool, Trace};
+///
+/// fn run(pool: &ThreadPool) -> Vec<u32> {
+///     let log = Arc::new(Mutex::new(Vec::new()));
+///     for i in 0..4 {
+///         let log = Arc::clone(&log);
+///         pool.execute(move || log.lock().unwrap().push(i)).unwrap();
+///     }
+///     pool.run_until_idle();
+///     let log = log.lock().unwrap().clone();
+///     log
+/// }
+///
+/// let pool = ThreadPool::builder().deterministic(7).build();
+/// let first = run(&pool);
+/// let trace = pool.trace().unwrap();
+///
+/// // 按记录重放得到相同的执行顺序
+/// let replay = ThreadPool::builder().replay(trace.to_string().parse().unwrap()).build();
+/// assert_eq!(run(&replay), first);
+///
+/// // 也可以手工指定顺序，构造特定的交错
+/// let forced = ThreadPool::builder().replay(Trace::new(0, vec![3, 2, 1, 0])).build();
+/// assert_eq!(run(&forced), [3, 2, 1, 0]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    seed        : u64,
+    order       : Vec<u64>,
+}
+
+impl Trace {
+    /// 先按 `order` 执行，之后的任务由 `seed` 决定顺序
+    pub fn new(seed: u64, order: Vec<u64>) -> Trace {
+        Trace { seed, order }
+    }
+
+    /// 随机数种子
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// 任务的执行顺序
+    pub fn order(&self) -> &[u64] {
+        &self.order
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.seed)?;
+        for (i, id) in self.order.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析执行记录时的错误
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("invalid trace: {0}")]
+pub struct ParseTraceError(String);
+
+impl FromStr for Trace {
+    type Err = ParseTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (seed, order) = s
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| ParseTraceError("missing ':' after seed".to_string()))?;
+        let seed = seed.parse().map_err(|_| ParseTraceError(format!("bad seed {:?}", seed)))?;
+        let order = order
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(|id| id.trim().parse().map_err(|_| ParseTraceError(format!("bad job id {:?}", id))))
+            .collect::<Result<_, _>>()?;
+        Ok(Trace { seed, order })
+    }
+}
+
+/// SplitMix64，任意种子都能得到良好分布的序列
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+struct State {
+    /// 待执行的任务，依次为执行记录中的编号、线程池分配的编号与任务，按提交顺序排列
+    pending     : Vec<(u64, u64, Job)>,
+    next_id     : u64,
+    rng         : Rng,
+    /// 重放时尚未使用的记录
+    replay      : VecDeque<u64>,
+    /// 已执行的任务编号
+    executed    : Vec<u64>,
+    closed      : bool,
+}
+
+/// 确定性模式的任务队列
+///
+/// 没有工作线程，由调用方通过 `ThreadPool::run_one` 逐个取出执行，
+/// 每次按重放记录或随机数从所有待执行的任务中选一个
+pub(crate) struct DeterministicQueue {
+    seed        : u64,
+    state       : Mutex<State>,
+}
+
+impl DeterministicQueue {
+    pub(crate) fn new(trace: Trace) -> DeterministicQueue {
+        DeterministicQueue {
+            seed: trace.seed,
+            state: Mutex::new(State {
+                pending: Vec::new(),
+                next_id: 0,
+                rng: Rng(trace.seed),
+                replay: trace.order.into(),
+                executed: Vec::new(),
+                closed: false,
+            }),
+        }
+    }
+
+    /// 选出下一个要执行的任务，重放的任务不在待执行列表中时 panic
+    pub(crate) fn next(&self) -> Option<Job> {
+        let mut state = lock(&self.state);
+        if state.pending.is_empty() {
+            return None;
+        }
+        let index = match state.replay.pop_front() {
+            Some(id) => {
+                let found = state.pending.iter().position(|(i, _, _)| *i == id);
+                match found {
+                    Some(index) => index,
+                    None => {
+                        drop(state);
+                        panic!("replay diverged: job {} is not pending", id);
+                    },
+                }
+            },
+            None => (state.rng.next() % state.pending.len() as u64) as usize,
+        };
+        let (id, _, job) = state.pending.remove(index);
+        state.executed.push(id);
+        Some(job)
+    }
+
+    pub(crate) fn trace(&self) -> Trace {
+        Trace::new(self.seed, lock(&self.state).executed.clone())
+    }
+}
+
+impl JobQueue for DeterministicQueue {
+    fn push(&self, job: Job, key: u64, _priority: i32, _local: Option<usize>) -> Result<(), Job> {
+        let mut state = lock(&self.state);
+        if state.closed {
+            return Err(job);
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending.push((id, key, job));
+        Ok(())
+    }
+
+    fn pop(&self, _worker: usize, _timeout: Duration) -> Pop {
+        // 确定性模式不创建工作线程
+        if lock(&self.state).closed { Pop::Closed } else { Pop::Idle }
+    }
+
+    fn wake(&self, _n: usize) {}
+
+    fn retire(&self, _worker: usize) {}
+
+    fn close(&self) {
+        lock(&self.state).closed = true;
+    }
+
+    fn drain(&self) -> Vec<Job> {
+        lock(&self.state).pending.drain(..).map(|(_, _, job)| job).collect()
+    }
+
+    fn pop_oldest(&self) -> Option<Job> {
+        let mut state = lock(&self.state);
+        (!state.pending.is_empty()).then(|| state.pending.remove(0).2)
+    }
+
+    fn remove(&self, key: u64) -> Option<Job> {
+        let mut state = lock(&self.state);
+        let index = state.pending.iter().position(|(_, k, _)| *k == key)?;
+        Some(state.pending.remove(index).2)
+    }
+
+    fn as_deterministic(&self) -> Option<&DeterministicQueue> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread::ThreadPool;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    /// 提交 `n` 个任务，每个任务再提交一个子任务，返回执行顺序
+    fn run(pool: &Arc<ThreadPool>, n: u32) -> Vec<u32> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..n {
+            let (p, log) = (Arc::clone(pool), Arc::clone(&log));
+            pool.execute(move || {
+                log.lock().unwrap().push(i);
+                let l = Arc::clone(&log);
+                p.execute(move || l.lock().unwrap().push(i + 100)).unwrap();
+            }).unwrap();
+        }
+        assert_eq!(pool.run_until_idle(), n as usize * 2);
+        let log = log.lock().unwrap().clone();
+        log
+    }
+
+    #[test]
+    fn test_seeded() {
+        let order = |seed| run(&Arc::new(ThreadPool::builder().deterministic(seed).build()), 8);
+        assert_eq!(order(42), order(42));
+        // 不同的种子得到不同的交错
+        let distinct: HashSet<_> = (0..16).map(order).collect();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    fn test_replay() {
+        let pool = Arc::new(ThreadPool::builder().deterministic(3).build());
+        let first = run(&pool, 6);
+        let trace = pool.trace().unwrap();
+        assert_eq!(trace.order().len(), 12);
+        let parsed: Trace = trace.to_string().parse().unwrap();
+        assert_eq!(parsed, trace);
+        let replay = Arc::new(ThreadPool::builder().replay(parsed).build());
+        assert_eq!(run(&replay, 6), first);
+        assert_eq!(replay.trace().unwrap(), trace);
+    }
+
+    #[test]
+    fn test_reproduce_race() {
+        // 先读后写拆成两个任务，模拟没有加锁的售票：两个读之间插入另一个读就会少卖
+        fn sell(pool: &Arc<ThreadPool>, tickets: &Arc<Mutex<u32>>) {
+            let (p, t) = (Arc::clone(pool), Arc::clone(tickets));
+            pool.execute(move || {
+                let seen = *t.lock().unwrap();
+                let t = Arc::clone(&t);
+                p.execute(move || *t.lock().unwrap() = seen + 1).unwrap();
+            }).unwrap();
+        }
+        let sold = |pool: &Arc<ThreadPool>| {
+            let tickets = Arc::new(Mutex::new(0));
+            for _ in 0..4 {
+                sell(pool, &tickets);
+            }
+            pool.run_until_idle();
+            let n = *tickets.lock().unwrap();
+            n
+        };
+        let (seed, lost) = (0..64)
+            .map(|seed| (seed, sold(&Arc::new(ThreadPool::builder().deterministic(seed).build()))))
+            .find(|&(_, n)| n < 4)
+            .expect("no seed exposes the race");
+        let pool = Arc::new(ThreadPool::builder().deterministic(seed).build());
+        assert_eq!(sold(&pool), lost);
+        // 按记录重放同样复现
+        let replay = Arc::new(ThreadPool::builder().replay(pool.trace().unwrap()).build());
+        assert_eq!(sold(&replay), lost);
+        // 指定串行的顺序则不会出错
+        let serial = Arc::new(ThreadPool::builder().replay(Trace::new(0, vec![0, 4, 1, 5, 2, 6, 3, 7])).build());
+        assert_eq!(sold(&serial), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "replay diverged")]
+    fn test_diverged() {
+        let pool = ThreadPool::builder().replay(Trace::new(0, vec![5])).build();
+        pool.execute(|| {}).unwrap();
+        pool.run_one();
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!("12".parse::<Trace>().is_err());
+        assert!("x:1".parse::<Trace>().is_err());
+        assert_eq!("1:".parse::<Trace>().unwrap(), Trace::new(1, vec![]));
+    }
+}
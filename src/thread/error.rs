@@ -0,0 +1,25 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use super::Job;
+
+/// 线程池提交任务时的错误
+#[derive(Error)]
+pub enum PoolError {
+    /// 线程池已关闭，不再接受新任务
+    #[error("thread pool has been shut down")]
+    Shutdown,
+    /// 队列已满，任务被拒绝，原样返回
+    #[error("job rejected because the queue is full")]
+    Rejected(Job),
+}
+
+impl fmt::Debug for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Shutdown => f.write_str("Shutdown"),
+            PoolError::Rejected(_) => f.write_str("Rejected(..)"),
+        }
+    }
+}
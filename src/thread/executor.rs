@@ -0,0 +1,531 @@
+//! 轻量的异步执行器
+//!
+//! `block_on` 在当前线程上驱动一个 `Future`，`Executor` 在线程池的工作线程上轮询任务，
+//! `sleep` 由一个全局的定时线程唤醒
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, TryLockError, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use super::{lock, task, JoinError, PoolError, ThreadPool};
+
+/// 唤醒时 unpark 对应的线程
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// 在当前线程上运行 `future` 直到完成
+///
+/// 在线程池的工作线程中调用会占用该线程直到完成
+///
+/// # Example
+/// ```
+/// use ptstd::thread::block_on;
+///
+/// assert_eq!(block_on(async { 1 + 1 }), 2);
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(r) = future.as_mut().poll(&mut cx) {
+            return r;
+        }
+        // unpark 先于 park 发生时 park 会立即返回，不会丢失唤醒
+        thread::park();
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// 执行器中的任务，被唤醒时把自身提交到线程池
+struct Task {
+    future      : Mutex<Option<BoxFuture>>,
+    /// 已在线程池中排队，避免重复提交
+    scheduled   : AtomicBool,
+    /// 唤醒时线程池队列已满，需要由持锁的一方重新轮询
+    repoll      : AtomicBool,
+    canceled    : AtomicBool,
+    pool        : Weak<ThreadPool>,
+}
+
+impl Task {
+    /// 提交到线程池，不按线程池的拒绝策略等待或在当前线程执行
+    ///
+    /// 队列已满时交给正在轮询的一方重新轮询，没有时在当前线程中轮询
+    fn schedule(self: Arc<Self>) {
+        if self.scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let Some(pool) = self.pool.upgrade() else {
+            self.cancel();
+            return;
+        };
+        let task = Arc::clone(&self);
+        match pool.try_execute(move || task.run()) {
+            Ok(()) => {},
+            Err(PoolError::Rejected(_)) => {
+                drop(pool);
+                // 先设置标记再尝试加锁，持锁的一方释放锁后一定能看到标记
+                self.repoll.store(true, Ordering::SeqCst);
+                if let Some(slot) = self.try_lock() {
+                    self.poll(slot);
+                }
+            },
+            Err(PoolError::Shutdown) => self.cancel(),
+        }
+    }
+
+    /// 执行器已关闭，丢弃 future，等待方得到 `JoinError::Canceled`
+    ///
+    /// 可能在轮询中被唤醒时调用，此时锁已被持有，由 `poll` 在轮询结束后丢弃
+    fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+        if let Some(mut slot) = self.try_lock() {
+            slot.take();
+        }
+    }
+
+    /// 不阻塞地获取 `future` 的锁，已被持有时返回 `None`
+    fn try_lock(&self) -> Option<MutexGuard<'_, Option<BoxFuture>>> {
+        match self.future.try_lock() {
+            Ok(slot) => Some(slot),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    fn run(self: Arc<Self>) {
+        let slot = lock(&self.future);
+        self.poll(slot);
+    }
+
+    /// 持锁轮询，轮询期间因队列已满未能提交的唤醒在本次轮询结束后重新轮询
+    fn poll<'a>(self: &'a Arc<Self>, mut slot: MutexGuard<'a, Option<BoxFuture>>) {
+        loop {
+            // 先清除标记，轮询期间的唤醒会再次提交，由锁保证同一时刻只有一处在轮询
+            self.repoll.store(false, Ordering::SeqCst);
+            self.scheduled.store(false, Ordering::SeqCst);
+            if let Some(future) = slot.as_mut() {
+                let waker = Waker::from(Arc::clone(self));
+                if future.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                    *slot = None;
+                }
+            }
+            drop(slot);
+            if self.canceled.load(Ordering::SeqCst) {
+                lock(&self.future).take();
+                return;
+            }
+            if !self.repoll.load(Ordering::SeqCst) {
+                return;
+            }
+            // 锁已被其他线程持有时由其重新轮询
+            match self.try_lock() {
+                Some(s) => slot = s,
+                None => return,
+            }
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+}
+
+struct JoinState<T> {
+    result      : Option<Result<T, JoinError>>,
+    waker       : Option<Waker>,
+}
+
+/// 写入任务结果，未写入就被丢弃时视为取消
+struct Completer<T> {
+    state       : Arc<Mutex<JoinState<T>>>,
+    done        : bool,
+}
+
+impl<T> Completer<T> {
+    fn complete(&mut self, result: Result<T, JoinError>) {
+        self.done = true;
+        let mut state = lock(&self.state);
+        state.result = Some(result);
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.complete(Err(JoinError::Canceled));
+        }
+    }
+}
+
+/// 异步任务的句柄，`await` 得到任务的输出
+///
+/// 任务 panic 时得到 `JoinError::Panicked`，执行器关闭导致任务被丢弃时得到 `JoinError::Canceled`
+pub struct JoinHandle<T> {
+    state       : Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+        match state.result.take() {
+            Some(r) => Poll::Ready(r),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// 捕获轮询时的 panic
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(Poll::Ready(r)) => Poll::Ready(Ok(r)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(JoinError::Panicked(task::panic_message(e.as_ref())))),
+        }
+    }
+}
+
+/// 多线程异步执行器，在线程池的工作线程上轮询任务
+///
+/// 执行器释放后线程池随之关闭，尚未完成的任务在下次被唤醒时取消
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use ptstd::thread::{block_on, sleep, Executor};
+///
+/// let executor = Executor::new(2);
+/// let h = executor.spawn(async {
+///     sleep(Duration::from_millis(10)).await;
+///     42
+/// });
+/// assert_eq!(block_on(h).unwrap(), 42);
+/// ```
+pub struct Executor {
+    pool        : Arc<ThreadPool>,
+}
+
+impl Executor {
+    /// 创建有 `max_worker` 个工作线程的执行器
+    pub fn new(max_worker: usize) -> Executor {
+        Self::with_pool(ThreadPool::new(max_worker))
+    }
+
+    /// 使用已有的线程池轮询任务
+    pub fn with_pool(pool: ThreadPool) -> Executor {
+        Executor { pool: Arc::new(pool) }
+    }
+
+    /// 内部的线程池，可直接提交同步任务
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    /// 提交一个异步任务
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(JoinState { result: None, waker: None }));
+        let mut completer = Completer { state: Arc::clone(&state), done: false };
+        let future = CatchUnwind(Box::pin(future));
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(async move {
+                let r = future.await;
+                completer.complete(r);
+            }))),
+            scheduled: AtomicBool::new(false),
+            repoll: AtomicBool::new(false),
+            canceled: AtomicBool::new(false),
+            pool: Arc::downgrade(&self.pool),
+        });
+        task.schedule();
+        JoinHandle { state }
+    }
+}
+
+/// 等待中的 `Sleep` 的唤醒位置，重复轮询时只更新其中的 waker
+type WakerSlot = Arc<Mutex<Option<Waker>>>;
+
+struct SleepEntry {
+    at          : Instant,
+    slot        : WakerSlot,
+}
+
+impl PartialEq for SleepEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at
+    }
+}
+
+impl Eq for SleepEntry {}
+
+impl PartialOrd for SleepEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SleepEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.at.cmp(&other.at)
+    }
+}
+
+/// 全局的定时线程，首次使用 `sleep` 时启动
+struct SleepTimer {
+    heap        : Mutex<BinaryHeap<Reverse<SleepEntry>>>,
+    cond        : Condvar,
+}
+
+impl SleepTimer {
+    fn get() -> &'static SleepTimer {
+        static TIMER: OnceLock<&'static SleepTimer> = OnceLock::new();
+        TIMER.get_or_init(|| {
+            let timer: &'static SleepTimer = Box::leak(Box::new(SleepTimer {
+                heap: Mutex::new(BinaryHeap::new()),
+                cond: Condvar::new(),
+            }));
+            thread::Builder::new()
+                .name("ptstd-timer".to_string())
+                .spawn(move || timer.run())
+                .expect("failed to spawn timer thread");
+            timer
+        })
+    }
+
+    fn register(&self, at: Instant, slot: WakerSlot) {
+        let mut heap = lock(&self.heap);
+        let earliest = heap.peek().is_none_or(|e| at < e.0.at);
+        heap.push(Reverse(SleepEntry { at, slot }));
+        drop(heap);
+        if earliest {
+            self.cond.notify_one();
+        }
+    }
+
+    fn run(&self) {
+        let mut heap = lock(&self.heap);
+        loop {
+            let now = Instant::now();
+            match heap.peek().map(|e| e.0.at) {
+                Some(at) if at <= now => {
+                    let Reverse(entry) = heap.pop().unwrap();
+                    drop(heap);
+                    // 先释放锁再唤醒，唤醒可能导致 `Sleep` 被释放而再次加锁
+                    let waker = lock(&entry.slot).take();
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                    heap = lock(&self.heap);
+                },
+                Some(at) => {
+                    heap = self.cond
+                        .wait_timeout(heap, at - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                },
+                None => {
+                    heap = self.cond.wait(heap).unwrap_or_else(PoisonError::into_inner);
+                },
+            }
+        }
+    }
+}
+
+/// `sleep` 返回的 future
+pub struct Sleep {
+    deadline    : Instant,
+    slot        : Option<WakerSlot>,
+}
+
+/// 等待 `duration` 后完成的 future，可用于 `block_on` 与 `Executor`
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep { deadline: Instant::now() + duration, slot: None }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.slot {
+            Some(slot) => *lock(slot) = Some(cx.waker().clone()),
+            None => {
+                let slot = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                SleepTimer::get().register(self.deadline, Arc::clone(&slot));
+                self.slot = Some(slot);
+            },
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // 提前释放 waker，不必等到时间到才释放任务
+        if let Some(slot) = &self.slot {
+            lock(slot).take();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc;
+    use crate::thread::RejectionPolicy;
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 40 + 2 }), 42);
+        let start = Instant::now();
+        block_on(sleep(Duration::from_millis(30)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_spawn() {
+        let executor = Executor::new(2);
+        let cnt = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..100)
+            .map(|i| {
+                let c = Arc::clone(&cnt);
+                executor.spawn(async move {
+                    sleep(Duration::from_millis(i % 10)).await;
+                    c.fetch_add(1, Ordering::SeqCst);
+                    i
+                })
+            })
+            .collect();
+        let sum: u64 = block_on(async {
+            let mut sum = 0;
+            for h in handles {
+                sum += h.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(sum, 4950);
+        assert_eq!(cnt.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_await_in_task() {
+        let executor = Executor::new(2);
+        let inner = executor.spawn(async { 7 });
+        let outer = executor.spawn(async move { inner.await.unwrap() * 6 });
+        assert_eq!(block_on(outer).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_spawn_panic() {
+        let executor = Executor::new(1);
+        let h = executor.spawn(async { panic!("async failed") });
+        assert!(matches!(block_on(h), Err(JoinError::Panicked(m)) if m == "async failed"));
+        assert_eq!(block_on(executor.spawn(async { 1 })).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_canceled_on_drop() {
+        let executor = Executor::new(1);
+        let h = executor.spawn(async {
+            sleep(Duration::from_millis(50)).await;
+            1
+        });
+        drop(executor);
+        assert!(matches!(block_on(h), Err(JoinError::Canceled)));
+    }
+
+    /// 第一次轮询时通知开始并阻塞到 `gate` 被关闭，之后每次轮询都唤醒自身，共让出 `n` 次
+    struct Yield {
+        n           : usize,
+        started     : Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
+    }
+
+    impl Future for Yield {
+        type Output = usize;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+            if let Some((started, gate)) = self.started.take() {
+                started.send(()).unwrap();
+                let _ = gate.recv();
+            }
+            if self.n == 0 {
+                return Poll::Ready(42);
+            }
+            self.n -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_wake_with_full_queue() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .rejection_policy(RejectionPolicy::CallerRuns)
+            .build();
+        let executor = Executor::with_pool(pool);
+        let (started, wait) = mpsc::channel();
+        let (gate, rx) = mpsc::channel::<()>();
+        let h = executor.spawn(Yield { n: 100, started: Some((started, rx)) });
+        wait.recv().unwrap();
+        // 轮询中唤醒自身时队列已满，不能在当前线程中重入轮询
+        executor.pool().execute(|| {}).unwrap();
+        drop(gate);
+        assert_eq!(block_on(h).unwrap(), 42);
+
+        // 工作线程被占用时在提交方线程中轮询
+        let (started, wait) = mpsc::channel();
+        let (gate, rx) = mpsc::channel::<()>();
+        executor.pool().execute(move || {
+            started.send(()).unwrap();
+            let _ = rx.recv();
+        }).unwrap();
+        wait.recv().unwrap();
+        executor.pool().execute(|| {}).unwrap();
+        let h = executor.spawn(Yield { n: 100, started: None });
+        assert_eq!(block_on(h).unwrap(), 42);
+        drop(gate);
+    }
+}
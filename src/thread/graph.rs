@@ -0,0 +1,408 @@
+use std::fmt::{Display, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use thiserror::Error;
+
+use super::{lock, task, Scope, ThreadPool};
+
+type GraphJob<'a> = Box<dyn FnOnce() -> Result<(), String> + Send + 'a>;
+
+/// 任务图中任务的编号，由 `TaskGraph::add_task` 返回
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+/// 任务的执行结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// 任务执行成功
+    Succeeded,
+    /// 任务返回错误或 panic，内容为错误信息
+    Failed(String),
+    /// 依赖的任务失败或被跳过，任务没有执行
+    Skipped,
+}
+
+/// 任务图的错误
+#[derive(Debug, Error)]
+pub enum GraphError {
+    /// 依赖存在环，内容为环上的任务名，前一个依赖后一个，首尾相同
+    #[error("dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+struct Node<'a> {
+    name        : String,
+    deps        : Vec<usize>,
+    job         : GraphJob<'a>,
+}
+
+/// 带依赖关系的任务图
+///
+/// 任务在依赖全部成功后执行，互不依赖的任务在线程池中并发执行。
+/// 任务失败时，所有直接或间接依赖它的任务都会被跳过
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{TaskGraph, TaskStatus, ThreadPool};
+///
+/// let pool = ThreadPool::new(2);
+/// let mut graph = TaskGraph::new();
+/// let a = graph.add_task("a", || Ok::<_, String>(()));
+/// let b = graph.add_task("b", || Err("b failed"));
+/// let c = graph.add_task("c", || Ok::<_, String>(()));
+/// graph.add_dependency(c, a);
+/// graph.add_dependency(c, b);
+///
+/// let outcome = graph.run(&pool).unwrap();
+/// assert_eq!(outcome.status(a), &TaskStatus::Succeeded);
+/// assert_eq!(outcome.status(c), &TaskStatus::Skipped);
+/// ```
+#[derive(Default)]
+pub struct TaskGraph<'a> {
+    nodes       : Vec<Node<'a>>,
+}
+
+impl<'a> TaskGraph<'a> {
+    /// 创建空的任务图
+    pub fn new() -> TaskGraph<'a> {
+        TaskGraph { nodes: Vec::new() }
+    }
+
+    /// 添加一个任务，返回 `Err` 或 panic 视为失败
+    pub fn add_task<F, E>(&mut self, name: impl Into<String>, f: F) -> TaskId
+    where
+        F: FnOnce() -> Result<(), E> + Send + 'a,
+        E: Display,
+    {
+        self.nodes.push(Node {
+            name: name.into(),
+            deps: Vec::new(),
+            job: Box::new(move || f().map_err(|e| e.to_string())),
+        });
+        TaskId(self.nodes.len() - 1)
+    }
+
+    /// `task` 在 `dep` 成功后才会执行，编号不属于该图时 panic
+    pub fn add_dependency(&mut self, task: TaskId, dep: TaskId) {
+        if task.0 >= self.nodes.len() || dep.0 >= self.nodes.len() {
+            panic!("task id out of range");
+        }
+        let deps = &mut self.nodes[task.0].deps;
+        if !deps.contains(&dep.0) {
+            deps.push(dep.0);
+        }
+    }
+
+    /// 任务数
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// 是否没有任务
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// 检查依赖是否有环
+    pub fn validate(&self) -> Result<(), GraphError> {
+        // 0 未访问，1 在当前路径上，2 已完成
+        let mut color = vec![0u8; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            if color[start] == 0 {
+                if let Some(cycle) = self.find_cycle(start, &mut color) {
+                    return Err(GraphError::Cycle(cycle));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 从 `start` 开始深度优先搜索，用显式的栈避免长依赖链耗尽线程栈
+    fn find_cycle(&self, start: usize, color: &mut [u8]) -> Option<Vec<String>> {
+        // 当前路径上的任务及下一个要访问的依赖下标
+        let mut path = vec![(start, 0)];
+        color[start] = 1;
+        while let Some((id, next)) = path.last_mut() {
+            let Some(&dep) = self.nodes[*id].deps.get(*next) else {
+                color[*id] = 2;
+                path.pop();
+                continue;
+            };
+            *next += 1;
+            match color[dep] {
+                0 => {
+                    color[dep] = 1;
+                    path.push((dep, 0));
+                },
+                1 => {
+                    let begin = path.iter().position(|&(n, _)| n == dep).unwrap();
+                    let mut cycle: Vec<_> = path[begin..].iter().map(|&(n, _)| self.nodes[n].name.clone()).collect();
+                    cycle.push(cycle[0].clone());
+                    return Some(cycle);
+                },
+                _ => {},
+            }
+        }
+        None
+    }
+
+    /// 导出为 Graphviz 的 DOT 格式，边从依赖指向依赖它的任务
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph TaskGraph {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = node.name.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = writeln!(dot, "    n{} [label=\"{}\"];", id, label);
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            for dep in &node.deps {
+                let _ = writeln!(dot, "    n{} -> n{};", dep, id);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// 在线程池中执行所有任务并等待结束，依赖有环时不执行任何任务
+    pub fn run(self, pool: &ThreadPool) -> Result<GraphOutcome, GraphError> {
+        self.validate()?;
+        let n = self.nodes.len();
+        let mut dependents = vec![Vec::new(); n];
+        let mut names = Vec::with_capacity(n);
+        let mut jobs = Vec::with_capacity(n);
+        let mut waiting = Vec::with_capacity(n);
+        for (id, node) in self.nodes.into_iter().enumerate() {
+            for &dep in &node.deps {
+                dependents[dep].push(id);
+            }
+            waiting.push(node.deps.len());
+            names.push(node.name);
+            jobs.push(Some(node.job));
+        }
+        let ready: Vec<_> = (0..n).filter(|&id| waiting[id] == 0).collect();
+        let run = Run {
+            dependents,
+            state: Mutex::new(RunState {
+                jobs,
+                waiting,
+                poisoned: vec![false; n],
+                statuses: vec![None; n],
+            }),
+        };
+        pool.scope(|s| {
+            for id in ready {
+                run.launch(s, id);
+            }
+        });
+        let statuses = run.state.into_inner().unwrap_or_else(|e| e.into_inner()).statuses;
+        Ok(GraphOutcome {
+            names,
+            statuses: statuses.into_iter().map(|s| s.expect("task not finished")).collect(),
+        })
+    }
+}
+
+struct RunState<'a> {
+    jobs        : Vec<Option<GraphJob<'a>>>,
+    /// 尚未结束的依赖数
+    waiting     : Vec<usize>,
+    /// 有依赖失败或被跳过
+    poisoned    : Vec<bool>,
+    statuses    : Vec<Option<TaskStatus>>,
+}
+
+/// 一次执行的共享状态
+struct Run<'a> {
+    dependents  : Vec<Vec<usize>>,
+    state       : Mutex<RunState<'a>>,
+}
+
+impl<'a> Run<'a> {
+    fn launch<'scope, 'env>(&'env self, s: &'scope Scope<'scope, 'env>, id: usize)
+    where
+        'a: 'env,
+    {
+        let job = lock(&self.state).jobs[id].take().unwrap();
+        s.spawn(move || {
+            let status = match panic::catch_unwind(AssertUnwindSafe(job)) {
+                Ok(Ok(())) => TaskStatus::Succeeded,
+                Ok(Err(e)) => TaskStatus::Failed(e),
+                Err(e) => TaskStatus::Failed(task::panic_message(e.as_ref())),
+            };
+            self.finish(s, id, status);
+        });
+    }
+
+    /// 记录结果并启动依赖已全部结束的任务
+    ///
+    /// 被跳过的任务放入工作列表逐个处理，失败沿长依赖链传播时不会递归
+    fn finish<'scope, 'env>(&'env self, s: &'scope Scope<'scope, 'env>, id: usize, status: TaskStatus)
+    where
+        'a: 'env,
+    {
+        let mut done = vec![(id, status)];
+        let mut ready = Vec::new();
+        let mut state = lock(&self.state);
+        while let Some((id, status)) = done.pop() {
+            let ok = status == TaskStatus::Succeeded;
+            state.statuses[id] = Some(status);
+            for &d in &self.dependents[id] {
+                if !ok {
+                    state.poisoned[d] = true;
+                }
+                state.waiting[d] -= 1;
+                if state.waiting[d] == 0 {
+                    if state.poisoned[d] {
+                        done.push((d, TaskStatus::Skipped));
+                    } else {
+                        ready.push(d);
+                    }
+                }
+            }
+        }
+        drop(state);
+        for d in ready {
+            self.launch(s, d);
+        }
+    }
+}
+
+/// 任务图的执行结果
+#[derive(Debug, Clone)]
+pub struct GraphOutcome {
+    names       : Vec<String>,
+    statuses    : Vec<TaskStatus>,
+}
+
+impl GraphOutcome {
+    /// 任务的执行结果
+    pub fn status(&self, id: TaskId) -> &TaskStatus {
+        &self.statuses[id.0]
+    }
+
+    /// 所有任务是否都成功
+    pub fn is_success(&self) -> bool {
+        self.statuses.iter().all(|s| *s == TaskStatus::Succeeded)
+    }
+
+    /// 按添加顺序遍历任务名与结果
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TaskStatus)> {
+        self.names.iter().map(String::as_str).zip(self.statuses.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn ok() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_order() {
+        let pool = ThreadPool::new(4);
+        let order = Mutex::new(Vec::new());
+        let mut graph = TaskGraph::new();
+        let push = |name: &'static str, delay: u64| {
+            let order = &order;
+            move || {
+                std::thread::sleep(Duration::from_millis(delay));
+                order.lock().unwrap().push(name);
+                ok()
+            }
+        };
+        let a = graph.add_task("a", push("a", 30));
+        let b = graph.add_task("b", push("b", 0));
+        let c = graph.add_task("c", push("c", 0));
+        let d = graph.add_task("d", push("d", 0));
+        graph.add_dependency(c, a);
+        graph.add_dependency(c, b);
+        graph.add_dependency(d, c);
+        let outcome = graph.run(&pool).unwrap();
+        assert!(outcome.is_success());
+        assert_eq!(*order.lock().unwrap(), ["b", "a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_failure_propagation() {
+        let pool = ThreadPool::new(2);
+        let mut graph = TaskGraph::new();
+        let a = graph.add_task("a", ok);
+        let b = graph.add_task("b", || -> Result<(), String> { panic!("b panicked") });
+        let c = graph.add_task("c", ok);
+        let d = graph.add_task("d", ok);
+        let e = graph.add_task("e", || Err("e failed"));
+        graph.add_dependency(c, b);
+        graph.add_dependency(d, c);
+        graph.add_dependency(d, a);
+        let outcome = graph.run(&pool).unwrap();
+        assert_eq!(outcome.status(a), &TaskStatus::Succeeded);
+        assert_eq!(outcome.status(b), &TaskStatus::Failed("b panicked".to_string()));
+        assert_eq!(outcome.status(c), &TaskStatus::Skipped);
+        assert_eq!(outcome.status(d), &TaskStatus::Skipped);
+        assert_eq!(outcome.status(e), &TaskStatus::Failed("e failed".to_string()));
+        assert!(!outcome.is_success());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let pool = ThreadPool::new(1);
+        let ran = Mutex::new(false);
+        let mut graph = TaskGraph::new();
+        let a = graph.add_task("a", || { *ran.lock().unwrap() = true; ok() });
+        let b = graph.add_task("b", ok);
+        let c = graph.add_task("c", ok);
+        graph.add_dependency(b, a);
+        graph.add_dependency(c, b);
+        graph.add_dependency(b, c);
+        match graph.run(&pool) {
+            Err(GraphError::Cycle(cycle)) => assert_eq!(cycle, ["b", "c", "b"]),
+            _ => panic!("expected cycle"),
+        }
+        assert!(!*ran.lock().unwrap());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut graph = TaskGraph::new();
+        let a = graph.add_task("fetch \"src\"", ok);
+        let b = graph.add_task("build", ok);
+        graph.add_dependency(b, a);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph TaskGraph {\n    n0 [label=\"fetch \\\"src\\\"\"];\n    n1 [label=\"build\"];\n    n0 -> n1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_long_chain() {
+        const N: usize = 100_000;
+        let pool = ThreadPool::new(2);
+        let mut graph = TaskGraph::new();
+        let head = graph.add_task("0", || Err("head failed"));
+        let mut prev = head;
+        for i in 1..N {
+            let id = graph.add_task(i.to_string(), ok);
+            graph.add_dependency(id, prev);
+            prev = id;
+        }
+        // 检查环与传播失败都不能按链长递归
+        graph.validate().unwrap();
+        let outcome = graph.run(&pool).unwrap();
+        assert_eq!(outcome.status(head), &TaskStatus::Failed("head failed".to_string()));
+        assert_eq!(outcome.iter().filter(|(_, s)| **s == TaskStatus::Skipped).count(), N - 1);
+
+        // 首尾相连后能找到整条链构成的环
+        let mut graph = TaskGraph::new();
+        let ids: Vec<_> = (0..N).map(|i| graph.add_task(i.to_string(), ok)).collect();
+        for i in 0..N {
+            graph.add_dependency(ids[i], ids[(i + 1) % N]);
+        }
+        match graph.validate() {
+            Err(GraphError::Cycle(cycle)) => assert_eq!(cycle.len(), N + 1),
+            _ => panic!("expected cycle"),
+        }
+    }
+}
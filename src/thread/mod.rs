@@ -1,119 +1,1370 @@
-use std::thread::{JoinHandle, self};
-use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::cell::Cell;
+use std::any::Any;
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+mod actor;
+mod builder;
+mod cancel;
+pub mod channel;
+mod deterministic;
+mod par;
+mod pipeline;
+mod error;
+mod executor;
+mod graph;
+mod queue;
+mod scheduled;
+mod scope;
+mod stats;
+pub mod sync;
+mod task;
+pub use actor::{Actor, ActorRef, MailboxClosed, Reply};
+pub use builder::ThreadPoolBuilder;
+use builder::WorkerHook;
+pub use cancel::CancellationToken;
+pub use deterministic::{ParseTraceError, Trace};
+pub use error::PoolError;
+pub use executor::{block_on, sleep, Executor, JoinHandle, Sleep};
+pub use graph::{GraphError, GraphOutcome, TaskGraph, TaskId, TaskStatus};
+pub use par::{par_chunks, par_for_each, par_map, par_reduce};
+pub use pipeline::{Pipeline, PipelineError, PipelineOutput};
+pub use queue::{RejectionPolicy, Scheduler};
+pub use scheduled::{ScheduledHandle, ScheduledThreadPool};
+pub use scope::Scope;
+pub use stats::{PoolStats, WorkerStats};
+pub use task::{JoinError, TaskHandle};
 
-enum Message {
-    NewJob(Job),
-    Stop,
+use deterministic::DeterministicQueue;
+use queue::{JobQueue, Pop, DEFAULT_PRIORITY};
+use stats::{Reporter, StatsHook, WorkerCounters};
+
+/// 线程池中的任务
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+thread_local! {
+    /// 当前线程所属的线程池及工作线程编号
+    static CURRENT_WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// 任务 panic 时传给钩子的信息
+#[derive(Debug, Clone)]
+pub struct JobPanic {
+    /// 发生 panic 的工作线程编号
+    pub worker_id: usize,
+    /// panic 信息
+    pub message: String,
+}
+
+type PanicHook = dyn Fn(&JobPanic) + Send + Sync + 'static;
+
+/// 线程池与工作线程共享的状态
+struct Shared {
+    queue       : Box<dyn JobQueue>,
+    workers     : Mutex<HashMap<usize, Worker>>,
+    panic_hook  : RwLock<Option<Arc<PanicHook>>>,
+    shutdown    : AtomicBool,
+    /// 排队中的任务数
+    queued      : AtomicUsize,
+    /// 下一个入队任务的编号，用于移除被取消的任务
+    next_key    : AtomicU64,
+    /// 正在等待任务的工作线程数
+    idle        : AtomicUsize,
+    core_size   : AtomicUsize,
+    max_size    : AtomicUsize,
+    keep_alive  : Duration,
+    /// 队列容量，`None` 为无界
+    capacity    : Option<usize>,
+    rejection   : RejectionPolicy,
+    /// 因队列已满而阻塞的提交方数量，有空位时通知 `space`
+    blocked     : AtomicUsize,
+    space_lock  : Mutex<()>,
+    space       : Condvar,
+    /// 存活的工作线程数，全部退出时通知 `exited`
+    alive       : AtomicUsize,
+    exit_lock   : Mutex<()>,
+    exited      : Condvar,
+    /// 正在执行的任务数
+    active      : AtomicUsize,
+    completed   : AtomicU64,
+    rejected    : AtomicU64,
+    panicked    : AtomicU64,
+    reporter    : Mutex<Option<Reporter>>,
+    config      : WorkerConfig,
+}
+
+impl Shared {
+    /// 当前线程若是本池的工作线程，返回其编号
+    fn current_worker(&self) -> Option<usize> {
+        let key = self as *const Shared as usize;
+        CURRENT_WORKER.with(|c| match c.get() {
+            Some((pool, id)) if pool == key => Some(id),
+            _ => None,
+        })
+    }
+
+    /// 任务 panic 后调用钩子
+    fn on_panic(&self, worker_id: usize, payload: Box<dyn Any + Send>) {
+        self.panicked.fetch_add(1, Ordering::Relaxed);
+        let hook = self.panic_hook.read().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(hook) = hook {
+            hook(&JobPanic { worker_id, message: task::panic_message(payload.as_ref()) });
+        }
+    }
+
+    /// 在存活线程数不超过 `limit` 的前提下增加一个工作线程，使用最小的空闲编号
+    fn spawn_worker(self: &Arc<Self>, limit: usize) -> bool {
+        let mut workers = lock(&self.workers);
+        // 确定性模式下任务只在调用方线程中执行
+        if self.shutdown.load(Ordering::SeqCst) || self.queue.as_deterministic().is_some() {
+            return false;
+        }
+        let reserved = self.alive
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |a| (a < limit).then_some(a + 1))
+            .is_ok();
+        if reserved {
+            let id = (0..).find(|id| !workers.contains_key(id)).unwrap();
+            workers.insert(id, Worker::new(id, Arc::clone(self), Arc::default()));
+        }
+        reserved
+    }
+
+    /// 执行一个已出队的任务并更新统计
+    fn run_job(&self, id: usize, job: Job, counters: Option<&WorkerCounters>) {
+        self.release();
+        self.active.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+        if let Some(counters) = counters {
+            counters.record(start.elapsed());
+        }
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        match result {
+            Ok(()) => { self.completed.fetch_add(1, Ordering::Relaxed); },
+            Err(e) => self.on_panic(id, e),
+        }
+    }
+
+    /// 提交任务后根据积压情况扩容
+    fn grow(self: &Arc<Self>) {
+        let alive = self.alive.load(Ordering::SeqCst);
+        let core = self.core_size.load(Ordering::SeqCst);
+        let max = self.max_size.load(Ordering::SeqCst);
+        if alive < core {
+            self.spawn_worker(core);
+        } else if alive < max && self.queued.load(Ordering::SeqCst) > self.idle.load(Ordering::SeqCst) {
+            self.spawn_worker(max);
+        }
+    }
+
+    /// 工作线程尝试退出
+    ///
+    /// 空闲超时的线程在超过核心线程数时退出，其余情况在超过最大线程数时退出
+    fn try_retire(&self, id: usize, idle: bool) -> bool {
+        let limit = if idle {
+            self.core_size.load(Ordering::SeqCst)
+        } else {
+            self.max_size.load(Ordering::SeqCst)
+        };
+        let retired = self.alive
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |a| (a > limit).then_some(a - 1))
+            .is_ok();
+        if !retired {
+            return false;
+        }
+        // 退出的同时可能有新任务到来却没有线程扩容，此时撤销退出
+        if idle
+            && self.queued.load(Ordering::SeqCst) > 0
+            && !self.shutdown.load(Ordering::SeqCst)
+        {
+            let max = self.max_size.load(Ordering::SeqCst);
+            let undo = self.alive
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |a| (a < max).then_some(a + 1))
+                .is_ok();
+            if undo {
+                return false;
+            }
+        }
+        self.queue.retire(id);
+        // 分离自身的句柄
+        lock(&self.workers).remove(&id);
+        self.notify_exit();
+        true
+    }
+
+    /// 为一个任务占用队列位置，队列已满时返回 `false`
+    fn reserve(&self) -> bool {
+        match self.capacity {
+            None => {
+                self.queued.fetch_add(1, Ordering::SeqCst);
+                true
+            },
+            Some(cap) => self.queued
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |q| (q < cap).then_some(q + 1))
+                .is_ok(),
+        }
+    }
+
+    /// 从队列中移除尚未开始的任务并释放其位置，任务已被取出时什么也不做
+    fn remove_queued(&self, key: u64) {
+        if let Some(job) = self.queue.remove(key) {
+            self.release();
+            drop(job);
+        }
+    }
+
+    fn next_key(&self) -> u64 {
+        self.next_key.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 释放一个队列位置
+    fn release(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            let _space = lock(&self.space_lock);
+            self.space.notify_one();
+        }
+    }
+
+    /// 阻塞直到队列可能有空位或线程池关闭
+    fn wait_space(&self) {
+        let cap = self.capacity.unwrap_or(usize::MAX);
+        let mut space = lock(&self.space_lock);
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        while self.queued.load(Ordering::SeqCst) >= cap && !self.shutdown.load(Ordering::SeqCst) {
+            space = self.space.wait(space).unwrap_or_else(PoisonError::into_inner);
+        }
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// 工作线程全部退出时通知等待方
+    fn notify_exit(&self) {
+        if self.alive.load(Ordering::SeqCst) == 0 {
+            let _exit = lock(&self.exit_lock);
+            self.exited.notify_all();
+        }
+    }
+
+    fn stats(&self) -> PoolStats {
+        let mut workers: Vec<_> = lock(&self.workers)
+            .values()
+            .map(|w| w.counters.snapshot(w.id))
+            .collect();
+        workers.sort_by_key(|w| w.id);
+        PoolStats {
+            queued: self.queued.load(Ordering::SeqCst),
+            active: self.active.load(Ordering::SeqCst),
+            completed: self.completed.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            workers,
+        }
+    }
 }
 
 /// 线程池
+///
+/// 工作线程数在核心线程数与最大线程数之间变化：
+/// 任务积压时扩容到最大线程数，超过核心线程数的线程空闲 `keep_alive` 后退出
 pub struct ThreadPool {
-    workers     : Vec<Worker>,
-    workers_len : usize,
-    sender      : mpsc::Sender<Message>,
+    shared      : Arc<Shared>,
 }
 
 impl ThreadPool {
+    /// 创建有 `max_worker` 个工作线程的线程池，使用默认的调度方式
     pub fn new(max_worker: usize) -> ThreadPool {
-        if max_worker == 0 {
+        ThreadPoolBuilder::new().num_workers(max_worker).build()
+    }
+
+    /// 通过构建器配置线程池
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    fn with_builder(builder: ThreadPoolBuilder) -> ThreadPool {
+        if builder.max_size == 0 {
             panic!("worker number should not be zero");
         }
+        if builder.core_size > builder.max_size {
+            panic!("core size should not exceed max size");
+        }
 
-        let (tx, rx) = mpsc::channel();
-        let mut workers = Vec::with_capacity(max_worker);
-        let receiver = Arc::new(Mutex::new(rx));
-        
-        for i in 0..max_worker {
-            let worker = Worker::new(i, Arc::clone(&receiver));
-            workers.push(worker);
+        let deterministic = builder.deterministic.is_some();
+        let shared = Arc::new(Shared {
+            queue: match builder.deterministic {
+                Some(trace) => Box::new(DeterministicQueue::new(trace)),
+                None => builder.scheduler.build(builder.max_size, builder.aging),
+            },
+            workers: Mutex::new(HashMap::with_capacity(builder.max_size)),
+            panic_hook: RwLock::new(None),
+            shutdown: AtomicBool::new(false),
+            queued: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            core_size: AtomicUsize::new(builder.core_size),
+            max_size: AtomicUsize::new(builder.max_size),
+            keep_alive: builder.keep_alive,
+            capacity: if deterministic { None } else { builder.capacity },
+            rejection: builder.rejection,
+            blocked: AtomicUsize::new(0),
+            space_lock: Mutex::new(()),
+            space: Condvar::new(),
+            alive: AtomicUsize::new(0),
+            exit_lock: Mutex::new(()),
+            exited: Condvar::new(),
+            active: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            next_key: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            reporter: Mutex::new(None),
+            config: WorkerConfig {
+                name_prefix: builder.name_prefix,
+                stack_size: builder.stack_size,
+                on_start: builder.on_start,
+                on_stop: builder.on_stop,
+                affinity: builder.affinity,
+            },
+        });
+
+        for _ in 0..builder.core_size {
+            shared.spawn_worker(builder.core_size);
         }
 
-        ThreadPool {
-            workers,
-            workers_len: max_worker,
-            sender: tx
+        ThreadPool { shared }
+    }
+
+    /// 提交一个任务，线程池关闭后返回 `PoolError::Shutdown`
+    ///
+    /// 有界队列已满时按构建时设置的 `RejectionPolicy` 处理。
+    /// 在工作线程中以 `Block` 策略提交可能因所有线程都在等待而死锁
+    pub fn execute<F>(&self, f: F) -> Result<(), PoolError> where F: FnOnce() + Send + 'static {
+        self.dispatch(Box::new(f), self.shared.next_key(), DEFAULT_PRIORITY, self.shared.rejection)
+    }
+
+    /// 以指定优先级提交任务，`priority` 越大越先执行，`execute` 的优先级为 0。
+    /// 相同优先级的任务按提交顺序执行
+    pub fn execute_with_priority<F>(&self, priority: i32, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.dispatch(Box::new(f), self.shared.next_key(), priority, self.shared.rejection)
+    }
+
+    /// 不阻塞地提交一个任务，队列已满时返回 `PoolError::Rejected`
+    pub fn try_execute<F>(&self, f: F) -> Result<(), PoolError> where F: FnOnce() + Send + 'static {
+        self.dispatch(Box::new(f), self.shared.next_key(), DEFAULT_PRIORITY, RejectionPolicy::Abort)
+    }
+
+    /// 提交一个可取消的任务，任务通过传入的令牌轮询或等待取消
+    ///
+    /// 令牌在任务开始前被取消时，任务会立即从队列中移除而不会执行，
+    /// 不再占用队列容量，也不计入完成数；提交时令牌已取消则直接丢弃任务
+    pub fn execute_cancellable<F>(&self, token: &CancellationToken, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let t = token.clone();
+        let key = self.shared.next_key();
+        let shared = Arc::downgrade(&self.shared);
+        let on_cancel = Box::new(move || {
+            if let Some(shared) = shared.upgrade() {
+                shared.remove_queued(key);
+            }
+        });
+        let Some(slot) = token.track(Box::new(move || f(&t)), on_cancel) else {
+            return Ok(());
+        };
+        let run = Box::new(move || {
+            let job = lock(&slot).job.take();
+            if let Some(job) = job {
+                job();
+            }
+        });
+        self.dispatch(run, key, DEFAULT_PRIORITY, self.shared.rejection)?;
+        // 在入队前取消时，令牌还找不到该任务
+        if token.is_cancelled() {
+            self.shared.remove_queued(key);
+        }
+        Ok(())
+    }
+
+    fn dispatch(&self, job: Job, key: u64, priority: i32, policy: RejectionPolicy) -> Result<(), PoolError> {
+        // 先占用位置再入队，工作线程取出后释放
+        let mut rejected = false;
+        loop {
+            if self.is_shutdown() {
+                return Err(PoolError::Shutdown);
+            }
+            if self.shared.reserve() {
+                break;
+            }
+            // 阻塞或丢弃最旧任务后可能多次重试，每个任务只计一次
+            if !rejected {
+                rejected = true;
+                self.shared.rejected.fetch_add(1, Ordering::Relaxed);
+            }
+            match policy {
+                RejectionPolicy::Block => self.shared.wait_space(),
+                RejectionPolicy::Abort => return Err(PoolError::Rejected(job)),
+                RejectionPolicy::CallerRuns => {
+                    job();
+                    return Ok(());
+                },
+                RejectionPolicy::DiscardOldest => {
+                    if let Some(oldest) = self.shared.queue.pop_oldest() {
+                        self.shared.release();
+                        drop(oldest);
+                    } else {
+                        // 队列刚被取空，重新占位即可
+                        thread::yield_now();
+                    }
+                },
+            }
+        }
+        if let Err(job) = self.shared.queue.push(job, key, priority, self.shared.current_worker()) {
+            self.shared.release();
+            drop(job);
+            return Err(PoolError::Shutdown);
         }
+        self.shared.grow();
+        Ok(())
     }
 
-    pub fn execute<F>(&self, f: F) where F: FnOnce() + Send + 'static {
-        let job = Message::NewJob(Box::new(f));
-        self.sender.send(job).unwrap();
+    /// 提交一个有返回值的任务，通过返回的 `TaskHandle` 取回结果
+    ///
+    /// 任务中的 panic 会被捕获，不会影响工作线程。
+    /// 线程池已关闭、任务被拒绝或被丢弃时，等待结果会得到 `JoinError::Canceled`。
+    /// 确定性模式下在任务中等待本线程池提交的其他任务不会有结果，`join` 返回 `JoinError::Deadlock`
+    pub fn submit<F, R>(&self, f: F) -> TaskHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = channel::oneshot::channel();
+        let _ = self.execute(move || match panic::catch_unwind(AssertUnwindSafe(f)) {
+            // 句柄可能已被丢弃，忽略发送失败
+            Ok(r) => { let _ = tx.send(Ok(r)); },
+            Err(e) => {
+                let _ = tx.send(Err(task::panic_message(e.as_ref())));
+                // 继续交给工作线程，统一触发 panic 钩子
+                panic::resume_unwind(e);
+            },
+        });
+        TaskHandle::new(rx).deterministic(self.shared.queue.as_deterministic().is_some())
     }
-}
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        // 给所有工作线程发送停止消息
-        // 发送对应数量的停止信号
-        for _ in 0..self.workers_len {
-            let message = Message::Stop;
-            self.sender.send(message).unwrap();
+    /// 创建一个作用域，其中提交的任务可以借用调用方栈上的数据
+    ///
+    /// 返回前会等待作用域内的所有任务结束，等待期间调用方也会执行任务。
+    /// 任务 panic 时，在所有任务结束后重新抛出
+    ///
+    /// # Example
+    /// ```
+    /// use ptstd::thread::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let data = vec![1, 2, 3, 4];
+    /// let mut doubled = vec![0; 4];
+    /// pool.scope(|s| {
+    ///     for (x, d) in data.iter().zip(doubled.iter_mut()) {
+    ///         s.spawn(move || *d = x * 2);
+    ///     }
+    /// });
+    /// assert_eq!(doubled, [2, 4, 6, 8]);
+    /// ```
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        scope::run(self, f)
+    }
+
+    /// 设置任务 panic 时的钩子，可用于记录日志或统计失败次数
+    ///
+    /// 钩子在发生 panic 的工作线程中调用；若钩子本身 panic，该工作线程会被重新拉起
+    pub fn set_panic_hook<F>(&self, hook: F) where F: Fn(&JobPanic) + Send + Sync + 'static {
+        *self.shared.panic_hook.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(hook));
+    }
+
+    /// 当前运行状态的快照
+    pub fn stats(&self) -> PoolStats {
+        self.shared.stats()
+    }
+
+    /// 每隔 `interval` 以当前的运行状态调用一次钩子，可用于导出监控数据
+    ///
+    /// 钩子在单独的线程中调用，重新设置会替换之前的钩子，线程池关闭后停止
+    pub fn set_stats_hook<F>(&self, interval: Duration, hook: F) where F: Fn(&PoolStats) + Send + Sync + 'static {
+        let hook: Box<StatsHook> = Box::new(hook);
+        let reporter = Reporter::spawn(Arc::downgrade(&self.shared), interval, hook);
+        if let Some(old) = lock(&self.shared.reporter).replace(reporter) {
+            old.stop();
+        }
+    }
+
+    /// 当前存活的工作线程数
+    pub fn num_workers(&self) -> usize {
+        self.shared.alive.load(Ordering::SeqCst)
+    }
+
+    /// 核心线程数
+    pub fn core_size(&self) -> usize {
+        self.shared.core_size.load(Ordering::SeqCst)
+    }
+
+    /// 最大线程数
+    pub fn max_size(&self) -> usize {
+        self.shared.max_size.load(Ordering::SeqCst)
+    }
+
+    /// 将线程池的核心线程数与最大线程数都调整为 `n`
+    ///
+    /// 扩容时立即创建新线程；缩容时空闲线程立即退出，忙碌的线程在当前任务结束后退出
+    pub fn resize(&self, n: usize) {
+        if n == 0 {
+            panic!("worker number should not be zero");
+        }
+        let old_max = self.shared.max_size.swap(n, Ordering::SeqCst);
+        self.shared.core_size.store(n, Ordering::SeqCst);
+        if n > old_max {
+            while self.shared.spawn_worker(n) {}
+        } else {
+            let alive = self.shared.alive.load(Ordering::SeqCst);
+            self.shared.queue.wake(alive.saturating_sub(n));
+        }
+    }
+
+    /// 确定性模式下在调用方线程中执行一个待执行的任务，没有任务时返回 `false`
+    ///
+    /// 任务 panic 时调用 panic 钩子，工作线程编号为 0；不是确定性模式时 panic。
+    /// 任务中只能用 `TaskHandle::try_join` 查询其他任务的结果，`join` 会返回 `JoinError::Deadlock`
+    pub fn run_one(&self) -> bool {
+        let Some(queue) = self.shared.queue.as_deterministic() else {
+            panic!("thread pool is not deterministic");
+        };
+        match queue.next() {
+            Some(job) => {
+                deterministic::run_in_job(|| self.shared.run_job(0, job, None));
+                true
+            },
+            None => false,
         }
+    }
+
+    /// 确定性模式下在调用方线程中执行任务，直到没有待执行的任务，返回执行的任务数
+    ///
+    /// 执行期间提交的任务也会被执行；不是确定性模式时 panic
+    pub fn run_until_idle(&self) -> usize {
+        let mut n = 0;
+        while self.run_one() {
+            n += 1;
+        }
+        n
+    }
+
+    /// 确定性模式下到目前为止的执行记录，其他模式返回 `None`
+    pub fn trace(&self) -> Option<Trace> {
+        self.shared.queue.as_deterministic().map(DeterministicQueue::trace)
+    }
+
+    /// 线程池是否已关闭
+    pub fn is_shutdown(&self) -> bool {
+        self.shared.shutdown.load(Ordering::SeqCst)
+    }
 
-        for w in &mut self.workers {
-            if let Some(t) = w.thread.take() {
-                t.join().unwrap();
+    /// 关闭线程池，不再接受新任务，等待队列中已有的任务全部执行完
+    pub fn shutdown(&self) {
+        self.stop_workers();
+        self.wait_workers(None);
+    }
+
+    /// 立即关闭线程池，丢弃队列中尚未开始的任务并返回，
+    /// 正在执行的任务会等待其结束
+    pub fn shutdown_now(&self) -> Vec<Job> {
+        self.stop_workers();
+        let jobs = self.shared.queue.drain();
+        for _ in 0..jobs.len() {
+            self.shared.release();
+        }
+        self.wait_workers(None);
+        jobs
+    }
+
+    /// 关闭线程池并最多等待 `timeout`
+    ///
+    /// 所有工作线程在超时前退出返回 `true`，否则返回 `false`，剩余线程执行完后自行退出
+    pub fn shutdown_timeout(&self, timeout: Duration) -> bool {
+        self.stop_workers();
+        self.wait_workers(Some(Instant::now() + timeout))
+    }
+
+    /// 标记关闭并关闭任务队列，工作线程取完剩余任务后退出
+    fn stop_workers(&self) {
+        // 持有线程表的锁，保证此后不会再创建新线程
+        let _workers = lock(&self.shared.workers);
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        self.shared.queue.close();
+        if let Some(reporter) = lock(&self.shared.reporter).take() {
+            reporter.stop();
+        }
+        // 唤醒因队列已满而阻塞的提交方
+        let _space = lock(&self.shared.space_lock);
+        self.shared.space.notify_all();
+    }
+
+    /// 等待所有工作线程退出，超时返回 `false`
+    fn wait_workers(&self, deadline: Option<Instant>) -> bool {
+        // 在工作线程中关闭自身所在的线程池时无法等待自己退出
+        if self.shared.current_worker().is_some() {
+            return false;
+        }
+        // 确定性模式没有工作线程，由调用方执行剩余的任务
+        if self.shared.queue.as_deterministic().is_some() {
+            self.run_until_idle();
+        }
+        let mut exit = lock(&self.shared.exit_lock);
+        while self.shared.alive.load(Ordering::SeqCst) > 0 {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    exit = self.shared.exited
+                        .wait_timeout(exit, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                },
+                None => {
+                    exit = self.shared.exited.wait(exit).unwrap_or_else(PoisonError::into_inner);
+                },
             }
         }
+        drop(exit);
+
+        // 此时线程都已结束，回收句柄
+        let threads: Vec<_> = lock(&self.shared.workers)
+            .values_mut()
+            .filter_map(|w| w.thread.take())
+            .collect();
+        for t in threads {
+            let _ = t.join();
+        }
+        true
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // 已经手动关闭过则不再等待，超时关闭后剩余的线程自行退出
+        if !self.is_shutdown() {
+            self.shutdown();
+        }
     }
 }
 
 struct Worker {
-    _id          : usize,
-    thread      : Option<JoinHandle<()>>,
+    id          : usize,
+    thread      : Option<thread::JoinHandle<()>>,
+    counters    : Arc<WorkerCounters>,
 }
 
 impl Worker {
-    fn new(
-        id: usize, 
-        receiver: Arc<Mutex<mpsc::Receiver<Message>>>
-    ) -> Worker {
-        let t = thread::spawn(move || {
+    fn new(id: usize, shared: Arc<Shared>, counters: Arc<WorkerCounters>) -> Worker {
+        let c = Arc::clone(&counters);
+        let mut builder = thread::Builder::new().name(format!("{}-{}", shared.config.name_prefix, id));
+        if let Some(size) = shared.config.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let t = builder.spawn(move || {
+            let mut sentinel = Sentinel {
+                id,
+                shared: Arc::clone(&shared),
+                counters: Arc::clone(&c),
+                retired: false,
+            };
+            CURRENT_WORKER.with(|c| c.set(Some((Arc::as_ptr(&shared) as usize, id))));
+            shared.config.start(id);
             loop {
-                let message = receiver.lock().unwrap().recv().unwrap();
-                match message {
-                    Message::NewJob(job) => {
-                        job();
+                shared.idle.fetch_add(1, Ordering::SeqCst);
+                let pop = shared.queue.pop(id, shared.keep_alive);
+                shared.idle.fetch_sub(1, Ordering::SeqCst);
+                let idle = match pop {
+                    Pop::Job(job) => {
+                        shared.run_job(id, job, Some(&c));
+                        false
                     },
-                    Message::Stop => break,
+                    Pop::Idle => true,
+                    Pop::Closed => break,
+                };
+                if shared.try_retire(id, idle) {
+                    sentinel.retired = true;
+                    break;
                 }
             }
-        });
-        Worker { _id: id, thread: Some(t) }
+        }).expect("failed to spawn worker thread");
+        Worker { id, thread: Some(t), counters }
+    }
+}
+
+/// 创建工作线程时使用的配置
+struct WorkerConfig {
+    name_prefix : String,
+    stack_size  : Option<usize>,
+    on_start    : Option<Arc<WorkerHook>>,
+    on_stop     : Option<Arc<WorkerHook>>,
+    /// 工作线程按编号轮流绑定的 CPU
+    affinity    : Vec<usize>,
+}
+
+impl WorkerConfig {
+    /// 在新的工作线程中绑核并调用启动钩子
+    fn start(&self, id: usize) {
+        if !self.affinity.is_empty() {
+            pin_to_cpu(self.affinity[id % self.affinity.len()]);
+        }
+        if let Some(hook) = &self.on_start {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(id)));
+        }
+    }
+
+    /// 调用退出钩子，钩子 panic 时也要继续完成退出的计数
+    fn stop(&self, id: usize) {
+        if let Some(hook) = &self.on_stop {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(id)));
+        }
+    }
+}
+
+/// 把当前线程绑定到指定 CPU，失败时忽略
+#[cfg(target_os = "linux")]
+fn pin_to_cpu(cpu: usize) {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return;
+    }
+    // SAFETY: cpu_set_t 是普通的位图，全零即为空集合，sched_setaffinity 只读取该集合
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_cpu(_cpu: usize) {}
+
+/// 工作线程守卫
+///
+/// 工作线程因 panic 退出时，在同一位置重新拉起一个工作线程
+struct Sentinel {
+    id      : usize,
+    shared  : Arc<Shared>,
+    counters: Arc<WorkerCounters>,
+    /// 已经通过缩容退出并完成计数
+    retired : bool,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        self.shared.config.stop(self.id);
+        if thread::panicking() {
+            // 持锁完成替换，保证等待退出的一方看到的总是新线程
+            let mut workers = lock(&self.shared.workers);
+            let worker = Worker::new(self.id, Arc::clone(&self.shared), Arc::clone(&self.counters));
+            workers.insert(self.id, worker);
+        } else if !self.retired {
+            self.shared.alive.fetch_sub(1, Ordering::SeqCst);
+            self.shared.notify_exit();
+        }
     }
 }
 
+/// 加锁，忽略锁中毒
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::mpsc;
     
-    fn sell(id: usize, ticket: Arc<Mutex<i32>>) {
+    fn sell(ticket: Arc<Mutex<i32>>) -> i32 {
         let mut n = 0;
         loop {
             let mut t = ticket.lock().unwrap();
             if *t < 10000 { 
                 *t += 1;
                 n += 1;
-                println!("machine {}: ticket {} sold", id, *t)
             } else {
                 break;
             }
         }
-        println!("machine {} sold {}", id, n);
+        n
     }
 
     #[test]
     fn test1() {
         let cnt = Arc::new(Mutex::new(0));
+        let sold = Arc::new(Mutex::new(Vec::new()));
         let max = 4;
-        let pool = ThreadPool::new(max);
-        for i in 0..max {
+        let pool = ThreadPool::builder().deterministic(1).build();
+        for _ in 0..max {
             let t = Arc::clone(&cnt);
+            let sold = Arc::clone(&sold);
+            pool.execute(move || {
+                let n = sell(t);
+                sold.lock().unwrap().push(n);
+            }).unwrap();
+        }
+        assert_eq!(pool.run_until_idle(), max);
+        assert_eq!(*cnt.lock().unwrap(), 10000);
+        let sold = sold.lock().unwrap();
+        assert_eq!(sold.len(), max);
+        assert_eq!(sold.iter().sum::<i32>(), 10000);
+    }
+
+    #[test]
+    fn test_deterministic_join_in_job() {
+        let pool = Arc::new(ThreadPool::builder().deterministic(3).build());
+        let p = Arc::clone(&pool);
+        let outer = pool.submit(move || p.submit(|| 1).join());
+        pool.run_one();
+        assert!(matches!(outer.join().unwrap(), Err(JoinError::Deadlock)));
+        // 内层任务仍在队列中，之后照常执行
+        assert_eq!(pool.run_until_idle(), 1);
+
+        // 已完成的任务在任务中可以正常取回结果
+        let done = pool.submit(|| 2);
+        pool.run_one();
+        let outer = pool.submit(move || done.join());
+        pool.run_one();
+        assert_eq!(outer.join().unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_submit() {
+        let pool = ThreadPool::new(4);
+        let handles: Vec<_> = (0..16).map(|i| pool.submit(move || i * i)).collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, (0..16).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_submit_panic() {
+        let pool = ThreadPool::new(1);
+        let h = pool.submit(|| -> i32 { panic!("job failed") });
+        match h.join() {
+            Err(JoinError::Panicked(msg)) => assert_eq!(msg, "job failed"),
+            _ => panic!("expected panic error"),
+        }
+        // 工作线程仍然可用
+        assert_eq!(pool.submit(|| 1).join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_submit_timeout() {
+        let pool = ThreadPool::new(1);
+        let h = pool.submit(|| {
+            thread::sleep(Duration::from_millis(200));
+            7
+        });
+        assert!(matches!(h.try_join(), Ok(None)));
+        assert!(matches!(h.join_timeout(Duration::from_millis(10)), Err(JoinError::Timeout)));
+        assert_eq!(h.join_timeout(Duration::from_secs(5)).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_panic_hook() {
+        let panicked = Arc::new(Mutex::new(Vec::new()));
+        let pool = ThreadPool::new(2);
+        let p = Arc::clone(&panicked);
+        pool.set_panic_hook(move |info| p.lock().unwrap().push(info.message.clone()));
+        for i in 0..3 {
+            pool.execute(move || panic!("job {}", i)).unwrap();
+        }
+        let h = pool.submit(|| -> () { panic!("submitted") });
+        assert!(h.join().is_err());
+        assert_eq!(pool.submit(|| 1).join().unwrap(), 1);
+        drop(pool);
+
+        let mut panicked = panicked.lock().unwrap().clone();
+        panicked.sort();
+        assert_eq!(panicked, vec!["job 0", "job 1", "job 2", "submitted"]);
+    }
+
+    #[test]
+    fn test_worker_respawn() {
+        let pool = ThreadPool::new(1);
+        pool.set_panic_hook(|_| panic!("hook failed"));
+        pool.execute(|| panic!("job failed")).unwrap();
+        // 唯一的工作线程崩溃后被重新拉起
+        assert_eq!(pool.submit(|| 2).join().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let cnt = Arc::new(Mutex::new(0));
+        let pool = ThreadPool::new(2);
+        for _ in 0..8 {
+            let c = Arc::clone(&cnt);
             pool.execute(move || {
-                sell(i, t);
-            });
+                thread::sleep(Duration::from_millis(10));
+                *c.lock().unwrap() += 1;
+            }).unwrap();
+        }
+        pool.shutdown();
+        assert_eq!(*cnt.lock().unwrap(), 8);
+        assert!(pool.is_shutdown());
+        assert!(matches!(pool.execute(|| ()), Err(PoolError::Shutdown)));
+        assert!(matches!(pool.submit(|| 1).join(), Err(JoinError::Canceled)));
+    }
+
+    #[test]
+    fn test_shutdown_now() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || {
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }).unwrap();
+        // 确保第一个任务已开始执行
+        rx.recv().unwrap();
+        let cnt = Arc::new(Mutex::new(0));
+        for _ in 0..5 {
+            let c = Arc::clone(&cnt);
+            pool.execute(move || *c.lock().unwrap() += 1).unwrap();
+        }
+        let pending = pool.shutdown_now();
+        assert_eq!(pending.len(), 5);
+        assert_eq!(*cnt.lock().unwrap(), 0);
+        for job in pending {
+            job();
+        }
+        assert_eq!(*cnt.lock().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_millis(300))).unwrap();
+        assert!(!pool.shutdown_timeout(Duration::from_millis(10)));
+
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_millis(10))).unwrap();
+        assert!(pool.shutdown_timeout(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_work_stealing() {
+        use std::sync::atomic::AtomicUsize;
+
+        let pool = Arc::new(ThreadPool::builder()
+            .num_workers(4)
+            .scheduler(Scheduler::WorkStealing)
+            .build());
+        let cnt = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..8).map(|_| {
+            let p = Arc::clone(&pool);
+            let c = Arc::clone(&cnt);
+            pool.submit(move || {
+                // 工作线程内提交的任务进入本地队列
+                for _ in 0..100 {
+                    let c = Arc::clone(&c);
+                    p.execute(move || { c.fetch_add(1, Ordering::SeqCst); }).unwrap();
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        pool.shutdown();
+        assert_eq!(cnt.load(Ordering::SeqCst), 800);
+        assert!(matches!(pool.execute(|| ()), Err(PoolError::Shutdown)));
+    }
+
+    #[test]
+    fn test_work_stealing_shutdown_now() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .scheduler(Scheduler::WorkStealing)
+            .build();
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || {
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }).unwrap();
+        rx.recv().unwrap();
+        for _ in 0..5 {
+            pool.execute(|| ()).unwrap();
+        }
+        assert_eq!(pool.shutdown_now().len(), 5);
+    }
+
+    /// 轮询直到条件成立，超时 panic
+    fn wait_until(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "condition not met in time");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_grow_and_shrink() {
+        use std::sync::atomic::AtomicUsize;
+
+        for scheduler in [Scheduler::Global, Scheduler::WorkStealing] {
+            let pool = ThreadPool::builder()
+                .core_size(1)
+                .max_size(4)
+                .keep_alive(Duration::from_millis(50))
+                .scheduler(scheduler)
+                .build();
+            assert_eq!(pool.num_workers(), 1);
+
+            let running = Arc::new(AtomicUsize::new(0));
+            let gate = Arc::new((Mutex::new(false), Condvar::new()));
+            for _ in 0..4 {
+                let running = Arc::clone(&running);
+                let gate = Arc::clone(&gate);
+                pool.execute(move || {
+                    running.fetch_add(1, Ordering::SeqCst);
+                    let (open, cond) = &*gate;
+                    let mut open = open.lock().unwrap();
+                    while !*open {
+                        open = cond.wait(open).unwrap();
+                    }
+                }).unwrap();
+            }
+            // 积压的任务使线程池扩容，4 个任务同时运行
+            wait_until(|| running.load(Ordering::SeqCst) == 4);
+            assert_eq!(pool.num_workers(), 4);
+
+            *gate.0.lock().unwrap() = true;
+            gate.1.notify_all();
+            // 空闲线程超时后退出，保留核心线程
+            wait_until(|| pool.num_workers() == 1);
+            assert_eq!(pool.submit(|| 3).join().unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        for scheduler in [Scheduler::Global, Scheduler::WorkStealing] {
+            let pool = ThreadPool::builder().num_workers(2).scheduler(scheduler).build();
+            assert_eq!(pool.num_workers(), 2);
+            pool.resize(4);
+            assert_eq!((pool.core_size(), pool.max_size(), pool.num_workers()), (4, 4, 4));
+            pool.resize(1);
+            wait_until(|| pool.num_workers() == 1);
+            let handles: Vec<_> = (0..8).map(|i| pool.submit(move || i)).collect();
+            let sum: i32 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+            assert_eq!(sum, 28);
+            pool.shutdown();
+            assert_eq!(pool.num_workers(), 0);
+        }
+    }
+
+    /// 占住唯一的工作线程，返回放行的闭包
+    fn block_worker(pool: &ThreadPool) -> impl FnOnce() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            let _ = gate_rx.recv();
+        }).unwrap();
+        started_rx.recv().unwrap();
+        move || drop(gate_tx)
+    }
+
+    #[test]
+    fn test_bounded_abort() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(2)
+            .rejection_policy(RejectionPolicy::Abort)
+            .build();
+        let open = block_worker(&pool);
+        let cnt = Arc::new(Mutex::new(0));
+        for _ in 0..2 {
+            let c = Arc::clone(&cnt);
+            pool.execute(move || *c.lock().unwrap() += 1).unwrap();
+        }
+        let c = Arc::clone(&cnt);
+        let rejected = match pool.execute(move || *c.lock().unwrap() += 1) {
+            Err(PoolError::Rejected(job)) => job,
+            _ => panic!("expected rejection"),
+        };
+        assert!(matches!(pool.try_execute(|| ()), Err(PoolError::Rejected(_))));
+        rejected();
+        open();
+        pool.shutdown();
+        assert_eq!(*cnt.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_bounded_block() {
+        let pool = Arc::new(ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .build());
+        let open = block_worker(&pool);
+        let done = Arc::new(AtomicBool::new(false));
+        let producer = {
+            let pool = Arc::clone(&pool);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    pool.execute(|| ()).unwrap();
+                }
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!done.load(Ordering::SeqCst));
+        // 非阻塞提交直接被拒绝
+        assert!(matches!(pool.try_execute(|| ()), Err(PoolError::Rejected(_))));
+        open();
+        producer.join().unwrap();
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_bounded_caller_runs() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .rejection_policy(RejectionPolicy::CallerRuns)
+            .build();
+        let open = block_worker(&pool);
+        pool.execute(|| ()).unwrap();
+        let caller = thread::current().id();
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(thread::current().id()).unwrap()).unwrap();
+        assert_eq!(rx.recv().unwrap(), caller);
+        open();
+    }
+
+    #[test]
+    fn test_bounded_discard_oldest() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(2)
+            .rejection_policy(RejectionPolicy::DiscardOldest)
+            .build();
+        let open = block_worker(&pool);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for i in 1..=3 {
+            let o = Arc::clone(&order);
+            pool.execute(move || o.lock().unwrap().push(i)).unwrap();
         }
+        open();
+        pool.shutdown();
+        assert_eq!(*order.lock().unwrap(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_priority() {
+        for scheduler in [Scheduler::Global, Scheduler::WorkStealing] {
+            let pool = ThreadPool::builder().num_workers(1).scheduler(scheduler).build();
+            let open = block_worker(&pool);
+            let order = Arc::new(Mutex::new(Vec::new()));
+            for (i, p) in [(1, 0), (2, 5), (3, -1), (4, 5), (5, 0)] {
+                let o = Arc::clone(&order);
+                pool.execute_with_priority(p, move || o.lock().unwrap().push(i)).unwrap();
+            }
+            open();
+            pool.shutdown();
+            // 高优先级先执行，相同优先级保持提交顺序
+            assert_eq!(*order.lock().unwrap(), vec![2, 4, 1, 5, 3]);
+        }
+    }
+
+    #[test]
+    fn test_priority_aging() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .aging(Duration::from_millis(10))
+            .build();
+        let open = block_worker(&pool);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let o = Arc::clone(&order);
+        pool.execute_with_priority(0, move || o.lock().unwrap().push("low")).unwrap();
+        // 等待 10 个老化周期，低优先级任务的有效优先级超过 5
+        thread::sleep(Duration::from_millis(100));
+        let o = Arc::clone(&order);
+        pool.execute_with_priority(5, move || o.lock().unwrap().push("high")).unwrap();
+        open();
+        pool.shutdown();
+        assert_eq!(*order.lock().unwrap(), vec!["low", "high"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let pool = ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .rejection_policy(RejectionPolicy::Abort)
+            .build();
+        let open = block_worker(&pool);
+        pool.execute(|| thread::sleep(Duration::from_millis(20))).unwrap();
+        assert!(pool.try_execute(|| ()).is_err());
+        let stats = pool.stats();
+        assert_eq!((stats.queued, stats.active, stats.rejected), (1, 1, 1));
+        open();
+        // 等待队列清空，否则下面的任务会因队列已满被拒绝
+        wait_until(|| pool.stats().queued == 0);
+        pool.submit(|| panic!("stats")).join().unwrap_err();
+        // 结果先于计数更新送达，等待计数完成
+        wait_until(|| pool.stats().panicked == 1 && pool.stats().active == 0);
+        let stats = pool.stats();
+        assert_eq!((stats.queued, stats.active), (0, 0));
+        assert_eq!((stats.completed, stats.panicked), (2, 1));
+        assert_eq!(stats.workers.len(), 1);
+        assert_eq!(stats.workers[0].jobs, 3);
+        assert!(stats.workers[0].busy >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_rejected_once() {
+        let pool = Arc::new(ThreadPool::builder()
+            .num_workers(1)
+            .queue_capacity(1)
+            .rejection_policy(RejectionPolicy::Block)
+            .build());
+        let open = block_worker(&pool);
+        pool.execute(|| ()).unwrap();
+        // 多个线程同时阻塞，争抢腾出的位置时会重试
+        let blocked: Vec<_> = (0..3).map(|_| {
+            let p = Arc::clone(&pool);
+            thread::spawn(move || p.execute(|| ()).unwrap())
+        }).collect();
+        wait_until(|| pool.stats().rejected == 3);
+        open();
+        for t in blocked {
+            t.join().unwrap();
+        }
+        pool.shutdown();
+        assert_eq!(pool.stats().rejected, 3);
+        assert_eq!(pool.stats().completed, 5);
+    }
+
+    #[test]
+    fn test_stats_hook() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        pool.set_stats_hook(Duration::from_millis(10), move |s| {
+            let _ = tx.lock().unwrap().send(s.completed);
+        });
+        pool.submit(|| ()).join().unwrap();
+        // 任务完成后的某次快照一定能看到它
+        while rx.recv_timeout(Duration::from_secs(5)).unwrap() < 1 {}
+        pool.shutdown();
+        // 关闭时可能正在执行最后一次钩子
+        thread::sleep(Duration::from_millis(20));
+        while rx.try_recv().is_ok() {}
+        thread::sleep(Duration::from_millis(50));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_worker_config() {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let (s1, s2) = (Arc::clone(&started), Arc::clone(&stopped));
+        let pool = ThreadPool::builder()
+            .num_workers(2)
+            .thread_name("test-pool")
+            .stack_size(256 * 1024)
+            .cpu_affinity([0])
+            .on_thread_start(move |id| s1.lock().unwrap().push(id))
+            .on_thread_stop(move |id| s2.lock().unwrap().push(id))
+            .build();
+        let name = pool.submit(|| thread::current().name().map(String::from)).join().unwrap();
+        assert!(matches!(name.as_deref(), Some("test-pool-0" | "test-pool-1")));
+        pool.shutdown();
+        let mut started = started.lock().unwrap().clone();
+        let mut stopped = stopped.lock().unwrap().clone();
+        started.sort();
+        stopped.sort();
+        assert_eq!(started, [0, 1]);
+        assert_eq!(stopped, [0, 1]);
+    }
+
+    #[test]
+    fn test_stop_hook_panic() {
+        let pool = ThreadPool::builder()
+            .num_workers(2)
+            .on_thread_stop(|_| panic!("stop hook"))
+            .build();
+        assert_eq!(pool.submit(|| 1).join().unwrap(), 1);
+        assert!(pool.shutdown_timeout(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_cancel_frees_capacity() {
+        for scheduler in [Scheduler::Global, Scheduler::WorkStealing] {
+            let pool = ThreadPool::builder()
+                .num_workers(1)
+                .queue_capacity(2)
+                .scheduler(scheduler)
+                .rejection_policy(RejectionPolicy::Abort)
+                .build();
+            let open = block_worker(&pool);
+            let token = CancellationToken::new();
+            for _ in 0..2 {
+                pool.execute_cancellable(&token, |_| panic!("cancelled job ran")).unwrap();
+            }
+            assert!(pool.try_execute(|| ()).is_err());
+            token.cancel();
+            // 取消的任务已移出队列，位置可以立即复用
+            assert_eq!(pool.stats().queued, 0);
+            pool.try_execute(|| ()).unwrap();
+            pool.try_execute(|| ()).unwrap();
+            open();
+            pool.shutdown();
+            let stats = pool.stats();
+            assert_eq!((stats.completed, stats.panicked, stats.rejected), (3, 0, 1));
+        }
+    }
+
+    #[test]
+    fn test_execute_cancellable() {
+        let pool = ThreadPool::new(1);
+        let open = block_worker(&pool);
+        let token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel::<()>();
+        let ran = Arc::new(Mutex::new(false));
+        let r = Arc::clone(&ran);
+        pool.execute_cancellable(&token.child_token(), move |_| {
+            let _tx = tx;
+            *r.lock().unwrap() = true;
+        }).unwrap();
+        token.cancel();
+        // 取消后任务在出队前就已被丢弃
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_err());
+        open();
+
+        let token = CancellationToken::new();
+        let (started_tx, started_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.execute_cancellable(&token, move |t| {
+            started_tx.send(()).unwrap();
+            t.wait();
+            done_tx.send(()).unwrap();
+        }).unwrap();
+        started_rx.recv().unwrap();
+        token.cancel();
+        done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        pool.execute_cancellable(&token, |_| panic!("should not run")).unwrap();
+        pool.shutdown();
+        assert!(!*ran.lock().unwrap());
+        assert_eq!(pool.stats().panicked, 0);
     }
 }
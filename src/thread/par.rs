@@ -0,0 +1,161 @@
+//! 基于线程池的并行辅助函数
+//!
+//! 输入被切分为若干块交给 `ThreadPool::scope` 执行，结果保持输入的顺序
+
+use super::ThreadPool;
+
+/// 每个工作线程分到的块数，块多一些可以平衡各块耗时不均的情况
+const CHUNKS_PER_WORKER: usize = 4;
+
+/// 把输入尽量均匀地切成不超过 `parts` 块，保持顺序
+fn split<T>(mut items: Vec<T>, parts: usize) -> Vec<Vec<T>> {
+    let size = items.len().div_ceil(parts.max(1)).max(1);
+    let mut chunks = Vec::with_capacity(items.len().div_ceil(size));
+    while items.len() > size {
+        let tail = items.split_off(items.len() - size);
+        chunks.push(tail);
+    }
+    if !items.is_empty() {
+        chunks.push(items);
+    }
+    chunks.reverse();
+    chunks
+}
+
+fn parts(pool: &ThreadPool) -> usize {
+    pool.num_workers().max(1) * CHUNKS_PER_WORKER
+}
+
+/// 并行地对每个元素调用 `f`，按输入顺序返回结果
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{par_map, ThreadPool};
+///
+/// let pool = ThreadPool::new(4);
+/// let data = vec![1, 2, 3, 4, 5];
+/// assert_eq!(par_map(&pool, &data, |x| x * x), [1, 4, 9, 16, 25]);
+/// ```
+pub fn par_map<I, R, F>(pool: &ThreadPool, items: I, f: F) -> Vec<R>
+where
+    I: IntoIterator,
+    I::Item: Send,
+    R: Send,
+    F: Fn(I::Item) -> R + Sync,
+{
+    let chunks = split(items.into_iter().collect(), parts(pool));
+    let mut out: Vec<Vec<R>> = chunks.iter().map(|c| Vec::with_capacity(c.len())).collect();
+    let f = &f;
+    pool.scope(|s| {
+        for (chunk, slot) in chunks.into_iter().zip(out.iter_mut()) {
+            s.spawn(move || slot.extend(chunk.into_iter().map(f)));
+        }
+    });
+    out.into_iter().flatten().collect()
+}
+
+/// 并行地对每个元素调用 `f`
+pub fn par_for_each<I, F>(pool: &ThreadPool, items: I, f: F)
+where
+    I: IntoIterator,
+    I::Item: Send,
+    F: Fn(I::Item) + Sync,
+{
+    let f = &f;
+    pool.scope(|s| {
+        for chunk in split(items.into_iter().collect(), parts(pool)) {
+            s.spawn(move || chunk.into_iter().for_each(f));
+        }
+    });
+}
+
+/// 把切片按 `chunk_size` 分块，并行地对每块调用 `f`，按块的顺序返回结果
+///
+/// `chunk_size` 为 0 时 panic
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{par_chunks, ThreadPool};
+///
+/// let pool = ThreadPool::new(4);
+/// let data: Vec<u32> = (1..=10).collect();
+/// assert_eq!(par_chunks(&pool, &data, 4, |c| c.iter().sum::<u32>()), [10, 26, 19]);
+/// ```
+pub fn par_chunks<T, R, F>(pool: &ThreadPool, items: &[T], chunk_size: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&[T]) -> R + Sync,
+{
+    if chunk_size == 0 {
+        panic!("chunk size should not be zero");
+    }
+    par_map(pool, items.chunks(chunk_size), f)
+}
+
+/// 并行归约
+///
+/// 每块从 `identity()` 开始依次用 `op` 合并，再按块的顺序合并各块结果，
+/// 因此 `op` 只需满足结合律，`identity()` 应是 `op` 的单位元
+///
+/// # Example
+/// ```
+/// use ptstd::thread::{par_reduce, ThreadPool};
+///
+/// let pool = ThreadPool::new(4);
+/// let words = vec!["a", "b", "c", "d"].into_iter().map(String::from);
+/// let s = par_reduce(&pool, words, String::new, |a, b| a + &b);
+/// assert_eq!(s, "abcd");
+/// ```
+pub fn par_reduce<I, T, ID, F>(pool: &ThreadPool, items: I, identity: ID, op: F) -> T
+where
+    I: IntoIterator<Item = T>,
+    T: Send,
+    ID: Fn() -> T + Sync,
+    F: Fn(T, T) -> T + Sync,
+{
+    let chunks = split(items.into_iter().collect(), parts(pool));
+    let partial = par_map(pool, chunks, |chunk| chunk.into_iter().fold(identity(), &op));
+    partial.into_iter().fold(identity(), &op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_split() {
+        let chunks = split((0..10).collect(), 3);
+        assert_eq!(chunks, [vec![0, 1], vec![2, 3, 4, 5], vec![6, 7, 8, 9]]);
+        assert!(split(Vec::<i32>::new(), 3).is_empty());
+        assert_eq!(split(vec![1, 2], 8), [vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_par_map_order() {
+        let pool = ThreadPool::new(4);
+        let data: Vec<u64> = (0..10_000).collect();
+        let out = par_map(&pool, &data, |x| x * 2);
+        assert_eq!(out, data.iter().map(|x| x * 2).collect::<Vec<_>>());
+        assert!(par_map(&pool, Vec::<u64>::new(), |x| x).is_empty());
+    }
+
+    #[test]
+    fn test_par_for_each() {
+        let pool = ThreadPool::new(4);
+        let sum = AtomicU64::new(0);
+        par_for_each(&pool, 1..=1000u64, |x| { sum.fetch_add(x, Ordering::SeqCst); });
+        assert_eq!(sum.load(Ordering::SeqCst), 500500);
+    }
+
+    #[test]
+    fn test_par_reduce() {
+        let pool = ThreadPool::new(3);
+        assert_eq!(par_reduce(&pool, 1..=1000u64, || 0, |a, b| a + b), 500500);
+        assert_eq!(par_reduce(&pool, Vec::<u64>::new(), || 0, |a, b| a + b), 0);
+        // 非交换的运算也保持顺序
+        let s = par_reduce(&pool, (0..100).map(|i| i.to_string()), String::new, |a, b| a + &b);
+        assert_eq!(s, (0..100).map(|i| i.to_string()).collect::<String>());
+    }
+}
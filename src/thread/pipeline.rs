@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use thiserror::Error;
+
+use super::channel::{self, Receiver, Sender};
+use super::{lock, task};
+
+/// 相邻两级之间通道的默认容量
+const DEFAULT_CAPACITY: usize = 64;
+
+/// 流水线的错误，`stage` 为出错的级，0 为数据源，第一个 `stage` 为 1
+#[derive(Debug, Error)]
+pub enum PipelineError<E> {
+    /// 某一级返回了错误
+    #[error("pipeline stage {stage} failed: {error}")]
+    Failed { stage: usize, error: E },
+    /// 某一级 panic，内容为 panic 信息
+    #[error("pipeline stage {stage} panicked: {message}")]
+    Panicked { stage: usize, message: String },
+}
+
+/// 带序号的数据，用于有序输出时重排
+type Packet<T, E> = (u64, Result<T, PipelineError<E>>);
+
+/// 各级共享的停止标记，出错或输出端被丢弃时置位
+struct Control {
+    stopped     : AtomicBool,
+    /// 数据源可以发出的序号上限，有序输出时随输出端推进
+    limit       : Mutex<u64>,
+    advanced    : Condvar,
+}
+
+impl Control {
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // 持锁通知，等待中的数据源不会错过
+        let _limit = lock(&self.limit);
+        self.advanced.notify_all();
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    fn advance(&self, limit: u64) {
+        *lock(&self.limit) = limit;
+        self.advanced.notify_all();
+    }
+
+    /// 等待序号 `seq` 可以发出，已停止时返回 `false`
+    fn acquire(&self, seq: u64) -> bool {
+        let mut limit = lock(&self.limit);
+        while seq >= *limit && !self.is_stopped() {
+            limit = self.advanced.wait(limit).unwrap_or_else(PoisonError::into_inner);
+        }
+        !self.is_stopped()
+    }
+}
+
+/// 多级流水线
+///
+/// 数据源与每一级都运行在各自的线程中，相邻两级之间通过有界通道连接，
+/// 下游处理不过来时上游阻塞。任意一级出错后整条流水线停止，错误从输出端返回
+///
+/// # Example
+/// ```
+/// use ptstd::thread::Pipeline;
+///
+/// let out: Result<Vec<u32>, _> = Pipeline::new(vec!["1", "2", "3"])
+///     .stage(2, |s: &str| s.parse::<u32>())
+///     .stage(2, |n| Ok(n * 10))
+///     .ordered()
+///     .collect();
+/// assert_eq!(out.unwrap(), [10, 20, 30]);
+/// ```
+pub struct Pipeline<T, E> {
+    rx          : Receiver<Packet<T, E>>,
+    control     : Arc<Control>,
+    threads     : Vec<JoinHandle<()>>,
+    capacity    : usize,
+    stages      : usize,
+}
+
+impl<T: Send + 'static, E: Send + 'static> Pipeline<T, E> {
+    /// 以 `source` 为数据源创建流水线，数据源在单独的线程中迭代
+    pub fn new<I>(source: I) -> Pipeline<T, E>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::with_capacity(DEFAULT_CAPACITY, source)
+    }
+
+    /// 指定相邻两级之间通道的容量，容量为 0 时 panic
+    pub fn with_capacity<I>(capacity: usize, source: I) -> Pipeline<T, E>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        if capacity == 0 {
+            panic!("capacity should not be zero");
+        }
+        // 选定输出方式前按有序输出限制数据源
+        let control = Arc::new(Control {
+            stopped: AtomicBool::new(false),
+            limit: Mutex::new(capacity as u64),
+            advanced: Condvar::new(),
+        });
+        let (tx, rx) = channel::bounded(capacity);
+        let iter = source.into_iter();
+        let c = Arc::clone(&control);
+        let t = thread::Builder::new()
+            .name("pipeline-source".to_string())
+            .spawn(move || Self::produce(iter, tx, c))
+            .expect("failed to spawn pipeline thread");
+        Pipeline { rx, control, threads: vec![t], capacity, stages: 0 }
+    }
+
+    fn produce(mut iter: impl Iterator<Item = T>, tx: Sender<Packet<T, E>>, control: Arc<Control>) {
+        for seq in 0.. {
+            if !control.acquire(seq) {
+                break;
+            }
+            let item = match panic::catch_unwind(AssertUnwindSafe(|| iter.next())) {
+                Ok(Some(item)) => Ok(item),
+                Ok(None) => break,
+                Err(e) => {
+                    control.stop();
+                    Err(PipelineError::Panicked { stage: 0, message: task::panic_message(e.as_ref()) })
+                },
+            };
+            let failed = item.is_err();
+            if tx.send((seq, item)).is_err() {
+                control.stop();
+                break;
+            }
+            if failed {
+                break;
+            }
+        }
+    }
+
+    /// 追加一级，由 `workers` 个线程并行调用 `f`，`workers` 为 0 时 panic
+    pub fn stage<U, F>(mut self, workers: usize, f: F) -> Pipeline<U, E>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> Result<U, E> + Send + Sync + 'static,
+    {
+        if workers == 0 {
+            panic!("worker number should not be zero");
+        }
+        let stage = self.stages + 1;
+        let (tx, rx) = channel::bounded(self.capacity);
+        let f = Arc::new(f);
+        for i in 0..workers {
+            let (input, tx, f) = (self.rx.clone(), tx.clone(), Arc::clone(&f));
+            let control = Arc::clone(&self.control);
+            let t = thread::Builder::new()
+                .name(format!("pipeline-{}-{}", stage, i))
+                .spawn(move || Self::work(stage, &input, &tx, &*f, &control))
+                .expect("failed to spawn pipeline thread");
+            self.threads.push(t);
+        }
+        Pipeline {
+            rx,
+            control: self.control,
+            threads: self.threads,
+            capacity: self.capacity,
+            stages: stage,
+        }
+    }
+
+    fn work<U>(
+        stage: usize,
+        input: &Receiver<Packet<T, E>>,
+        tx: &Sender<Packet<U, E>>,
+        f: &(dyn Fn(T) -> Result<U, E> + Send + Sync),
+        control: &Control,
+    ) {
+        loop {
+            let Ok((seq, item)) = input.recv() else { break };
+            let out = match item {
+                // 上游的错误原样向下传递
+                Err(e) => Err(e),
+                // 已停止时丢弃剩余数据，尽快让上游退出
+                Ok(_) if control.is_stopped() => continue,
+                Ok(v) => match panic::catch_unwind(AssertUnwindSafe(|| f(v))) {
+                    Ok(Ok(u)) => Ok(u),
+                    Ok(Err(error)) => {
+                        control.stop();
+                        Err(PipelineError::Failed { stage, error })
+                    },
+                    Err(e) => {
+                        control.stop();
+                        Err(PipelineError::Panicked { stage, message: task::panic_message(e.as_ref()) })
+                    },
+                },
+            };
+            if tx.send((seq, out)).is_err() {
+                control.stop();
+                break;
+            }
+        }
+    }
+
+    /// 按数据源的顺序输出
+    ///
+    /// 数据源最多领先输出端一个通道容量的序号，某个数据处理得慢时上游随之阻塞，
+    /// 先完成的数据最多缓存这么多个
+    pub fn ordered(self) -> PipelineOutput<T, E> {
+        self.output(true)
+    }
+
+    /// 按处理完成的顺序输出
+    pub fn unordered(self) -> PipelineOutput<T, E> {
+        self.output(false)
+    }
+
+    fn output(self, ordered: bool) -> PipelineOutput<T, E> {
+        if !ordered {
+            self.control.advance(u64::MAX);
+        }
+        PipelineOutput {
+            rx: Some(self.rx),
+            control: self.control,
+            threads: self.threads,
+            ordered,
+            next_seq: 0,
+            window: self.capacity as u64,
+            buffer: BTreeMap::new(),
+            done: false,
+        }
+    }
+}
+
+/// 流水线的输出端
+///
+/// 遇到错误时返回该错误后结束；提前丢弃时停止整条流水线并等待各级线程退出
+pub struct PipelineOutput<T, E> {
+    rx          : Option<Receiver<Packet<T, E>>>,
+    control     : Arc<Control>,
+    threads     : Vec<JoinHandle<()>>,
+    ordered     : bool,
+    /// 有序输出时下一个应输出的序号
+    next_seq    : u64,
+    /// 有序输出时数据源可以领先 `next_seq` 的序号数
+    window      : u64,
+    /// 有序输出时先到达的数据
+    buffer      : BTreeMap<u64, T>,
+    done        : bool,
+}
+
+impl<T, E> PipelineOutput<T, E> {
+    fn finish(&mut self) {
+        self.done = true;
+        self.control.stop();
+        self.buffer.clear();
+        // 先关闭接收端，阻塞在发送上的线程才能退出
+        self.rx = None;
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+    }
+}
+
+impl<T, E> Iterator for PipelineOutput<T, E> {
+    type Item = Result<T, PipelineError<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            if let Some(v) = self.buffer.remove(&self.next_seq) {
+                self.next_seq += 1;
+                self.control.advance(self.next_seq + self.window);
+                return Some(Ok(v));
+            }
+            let packet = self.rx.as_ref().and_then(|rx| rx.recv().ok());
+            match packet {
+                Some((_, Err(e))) => {
+                    self.finish();
+                    return Some(Err(e));
+                },
+                Some((_, Ok(v))) if !self.ordered => return Some(Ok(v)),
+                Some((seq, Ok(v))) => {
+                    self.buffer.insert(seq, v);
+                },
+                None => {
+                    self.finish();
+                    return None;
+                },
+            }
+        }
+    }
+}
+
+impl<T, E> Drop for PipelineOutput<T, E> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ordered() {
+        let out: Vec<_> = Pipeline::<_, String>::with_capacity(4, 0..200u64)
+            .stage(4, |n| {
+                // 让各线程完成的顺序与输入不同
+                thread::sleep(Duration::from_micros((n * 7919) % 500));
+                Ok(n * 2)
+            })
+            .stage(3, |n| Ok(n + 1))
+            .ordered()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(out, (0..200).map(|n| n * 2 + 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unordered() {
+        let mut out: Vec<_> = Pipeline::<_, String>::new(0..100u32)
+            .stage(4, |n| Ok(n * n))
+            .unordered()
+            .map(Result::unwrap)
+            .collect();
+        out.sort();
+        assert_eq!(out, (0..100).map(|n| n * n).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_error_stops_pipeline() {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let p = Arc::clone(&produced);
+        let source = (0..).inspect(move |_| { p.fetch_add(1, Ordering::SeqCst); });
+        let mut out = Pipeline::with_capacity(2, source)
+            .stage(2, |n: u64| if n == 10 { Err("bad record") } else { Ok(n) })
+            .stage(1, Ok)
+            .ordered();
+        let mut last = None;
+        for r in &mut out {
+            match r {
+                Ok(n) => last = Some(n),
+                Err(e) => {
+                    assert!(matches!(e, PipelineError::Failed { stage: 1, error: "bad record" }));
+                    break;
+                },
+            }
+        }
+        assert!(last.is_none_or(|n| n < 10));
+        assert!(out.next().is_none());
+        // 无限的数据源也已停止
+        assert!(produced.load(Ordering::SeqCst) < 1000);
+    }
+
+    #[test]
+    fn test_panic() {
+        let r: Result<Vec<_>, _> = Pipeline::<_, String>::new(0..10)
+            .stage(2, |n: i32| if n == 3 { panic!("stage panicked") } else { Ok(n) })
+            .unordered()
+            .collect();
+        assert!(matches!(r, Err(PipelineError::Panicked { stage: 1, message }) if message == "stage panicked"));
+    }
+
+    #[test]
+    fn test_drop_output() {
+        let out = Pipeline::<_, String>::with_capacity(1, 0..)
+            .stage(2, |n: u64| Ok(n))
+            .unordered();
+        let first: Vec<_> = out.take(5).collect();
+        // 丢弃输出端后各级线程都已退出
+        assert_eq!(first.len(), 5);
+    }
+
+    #[test]
+    fn test_ordered_slow_item() {
+        const CAPACITY: usize = 4;
+        let produced = Arc::new(AtomicUsize::new(0));
+        let p = Arc::clone(&produced);
+        let source = (0..1000u64).inspect(move |_| { p.fetch_add(1, Ordering::SeqCst); });
+        let seen = Arc::new(AtomicUsize::new(0));
+        let s = Arc::clone(&seen);
+        let p = Arc::clone(&produced);
+        let out: Vec<_> = Pipeline::<_, String>::with_capacity(CAPACITY, source)
+            .stage(4, move |n| {
+                if n == 0 {
+                    thread::sleep(Duration::from_millis(100));
+                    s.store(p.load(Ordering::SeqCst), Ordering::SeqCst);
+                }
+                Ok(n)
+            })
+            .ordered()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(out, (0..1000).collect::<Vec<_>>());
+        // 第一个数据处理完之前，数据源不会领先超过一个通道容量
+        assert!(seen.load(Ordering::SeqCst) <= CAPACITY);
+    }
+}
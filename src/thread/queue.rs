@@ -0,0 +1,518 @@
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::deterministic::DeterministicQueue;
+use super::{lock, Job};
+
+/// 任务调度方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduler {
+    /// 所有工作线程共享一个加锁的优先队列
+    #[default]
+    Global,
+    /// 每个工作线程持有本地队列，外部提交的任务进入全局队列，
+    /// 工作线程空闲时从全局队列批量领取或从其他线程的本地队列窃取。
+    /// 工作线程内提交的默认优先级任务进入本地队列，优先级只在全局队列中生效
+    WorkStealing,
+}
+
+/// 有界队列已满时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RejectionPolicy {
+    /// 阻塞提交方直到队列有空位
+    #[default]
+    Block,
+    /// 拒绝任务，返回 `PoolError::Rejected`
+    Abort,
+    /// 在提交方线程中直接执行任务
+    CallerRuns,
+    /// 丢弃队列中最早的任务后再放入
+    DiscardOldest,
+}
+
+/// 工作线程取任务的结果
+pub(crate) enum Pop {
+    /// 取到任务
+    Job(Job),
+    /// 等待超时或被唤醒，需要重新检查线程池状态
+    Idle,
+    /// 队列已关闭且为空
+    Closed,
+}
+
+/// 线程池内部的任务队列
+pub(crate) trait JobQueue: Send + Sync {
+    /// 放入任务，`key` 为线程池分配的唯一编号，`priority` 越大越先执行，
+    /// `local` 为提交任务的工作线程编号（若由本池的工作线程提交）。
+    /// 队列关闭后原样返回任务
+    fn push(&self, job: Job, key: u64, priority: i32, local: Option<usize>) -> Result<(), Job>;
+    /// 工作线程阻塞取任务，最多等待 `timeout`
+    fn pop(&self, worker: usize, timeout: Duration) -> Pop;
+    /// 唤醒至多 `n` 个正在等待的工作线程，使其返回 `Pop::Idle`
+    fn wake(&self, n: usize);
+    /// 工作线程退出前调用，交出其持有的任务
+    fn retire(&self, worker: usize);
+    /// 关闭队列，不再接受新任务，已有任务仍可取出
+    fn close(&self);
+    /// 取出所有尚未开始的任务
+    fn drain(&self) -> Vec<Job>;
+    /// 取出最早放入的一个任务
+    fn pop_oldest(&self) -> Option<Job>;
+    /// 移除编号为 `key` 的任务，已被取出时返回 `None`
+    fn remove(&self, key: u64) -> Option<Job>;
+    /// 确定性模式的队列返回自身
+    fn as_deterministic(&self) -> Option<&DeterministicQueue> {
+        None
+    }
+}
+
+impl Scheduler {
+    pub(crate) fn build(self, workers: usize, aging: Option<Duration>) -> Box<dyn JobQueue> {
+        match self {
+            Scheduler::Global => Box::new(GlobalQueue::new(aging)),
+            Scheduler::WorkStealing => Box::new(StealingQueue::new(workers, aging)),
+        }
+    }
+}
+
+/// 默认的任务优先级
+pub(crate) const DEFAULT_PRIORITY: i32 = 0;
+
+/// 优先队列中的任务
+struct Entry {
+    /// 排序键，越大越先执行
+    key     : i128,
+    /// 放入顺序，键相同时先放入的先执行
+    seq     : u64,
+    /// 线程池分配的编号
+    id      : u64,
+    job     : Job,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// 按优先级排序的任务堆
+///
+/// 开启老化后，任务每等待 `aging` 时长，有效优先级提升 1。
+/// 两个任务在任意时刻的有效优先级之差 `(p1 - t1 / aging) - (p2 - t2 / aging)`
+/// 与当前时间无关，因此可以在放入时算出固定的排序键
+struct PriorityHeap {
+    heap    : BinaryHeap<Entry>,
+    seq     : u64,
+    aging   : Option<Duration>,
+    epoch   : Instant,
+}
+
+impl PriorityHeap {
+    fn new(aging: Option<Duration>) -> PriorityHeap {
+        PriorityHeap {
+            heap: BinaryHeap::new(),
+            seq: 0,
+            aging,
+            epoch: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, job: Job, id: u64, priority: i32) {
+        let key = match self.aging {
+            Some(aging) => {
+                let aging = aging.as_nanos().max(1) as i128;
+                let waited = self.epoch.elapsed().as_nanos() as i128;
+                priority as i128 * aging - waited
+            },
+            None => priority as i128,
+        };
+        self.seq += 1;
+        self.heap.push(Entry { key, seq: self.seq, id, job });
+    }
+
+    fn pop(&mut self) -> Option<Job> {
+        self.heap.pop().map(|e| e.job)
+    }
+
+    /// 与 `pop` 相同，同时返回编号
+    fn pop_entry(&mut self) -> Option<(u64, Job)> {
+        self.heap.pop().map(|e| (e.id, e.job))
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn drain(&mut self) -> Vec<Job> {
+        self.heap.drain().map(|e| e.job).collect()
+    }
+
+    /// 取出最早放入的任务
+    fn pop_oldest(&mut self) -> Option<Job> {
+        let oldest = self.heap.iter().min_by_key(|e| e.seq).map(|e| e.id)?;
+        self.remove(oldest)
+    }
+
+    /// 取出编号为 `id` 的任务，需要重建堆
+    fn remove(&mut self, id: u64) -> Option<Job> {
+        if !self.heap.iter().any(|e| e.id == id) {
+            return None;
+        }
+        let mut entries = std::mem::take(&mut self.heap).into_vec();
+        let index = entries.iter().position(|e| e.id == id)?;
+        let job = entries.swap_remove(index).job;
+        self.heap = BinaryHeap::from(entries);
+        Some(job)
+    }
+}
+
+struct GlobalState {
+    heap    : PriorityHeap,
+    closed  : bool,
+    /// 尚未被消费的唤醒次数
+    wakes   : usize,
+    /// 正在等待的工作线程数
+    waiting : usize,
+    /// 已通知但尚未醒来的工作线程数
+    signaled: usize,
+}
+
+/// 加锁的全局优先队列，由所有工作线程共享
+struct GlobalQueue {
+    state   : Mutex<GlobalState>,
+    ready   : Condvar,
+}
+
+impl GlobalQueue {
+    fn new(aging: Option<Duration>) -> GlobalQueue {
+        GlobalQueue {
+            state: Mutex::new(GlobalState {
+                heap: PriorityHeap::new(aging),
+                closed: false,
+                wakes: 0,
+                waiting: 0,
+                signaled: 0,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+}
+
+impl JobQueue for GlobalQueue {
+    fn push(&self, job: Job, key: u64, priority: i32, _local: Option<usize>) -> Result<(), Job> {
+        let mut state = lock(&self.state);
+        if state.closed {
+            return Err(job);
+        }
+        state.heap.push(job, key, priority);
+        // 已通知的线程足够处理积压任务时不再重复通知，减少线程切换
+        let notify = state.waiting > state.signaled && state.heap.len() > state.signaled;
+        if notify {
+            state.signaled += 1;
+        }
+        drop(state);
+        if notify {
+            self.ready.notify_one();
+        }
+        Ok(())
+    }
+
+    fn pop(&self, _worker: usize, timeout: Duration) -> Pop {
+        let deadline = Instant::now() + timeout;
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(job) = state.heap.pop() {
+                return Pop::Job(job);
+            }
+            if state.closed {
+                return Pop::Closed;
+            }
+            if state.wakes > 0 {
+                state.wakes -= 1;
+                return Pop::Idle;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Pop::Idle;
+            }
+            state.waiting += 1;
+            state = self.ready
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            state.waiting -= 1;
+            state.signaled = state.signaled.saturating_sub(1);
+        }
+    }
+
+    fn wake(&self, n: usize) {
+        let mut state = lock(&self.state);
+        state.wakes += n;
+        state.signaled = state.waiting;
+        drop(state);
+        self.ready.notify_all();
+    }
+
+    fn retire(&self, _worker: usize) {}
+
+    fn close(&self) {
+        let mut state = lock(&self.state);
+        state.closed = true;
+        state.signaled = state.waiting;
+        drop(state);
+        self.ready.notify_all();
+    }
+
+    fn drain(&self) -> Vec<Job> {
+        lock(&self.state).heap.drain()
+    }
+
+    fn pop_oldest(&self) -> Option<Job> {
+        lock(&self.state).heap.pop_oldest()
+    }
+
+    fn remove(&self, key: u64) -> Option<Job> {
+        lock(&self.state).heap.remove(key)
+    }
+}
+
+/// 工作线程的本地队列，元素为任务编号与任务
+type LocalDeque = VecDeque<(u64, Job)>;
+
+/// 工作窃取队列
+///
+/// 工作线程优先从自己的本地队列尾部取任务，其次从全局队列批量领取，
+/// 最后从其他线程本地队列的头部窃取
+struct StealingQueue {
+    injector    : Mutex<PriorityHeap>,
+    /// 按工作线程编号索引，线程池扩容时增长
+    locals      : RwLock<Vec<Mutex<LocalDeque>>>,
+    /// 所有队列中的任务总数
+    pending     : AtomicUsize,
+    /// 正在休眠的工作线程数
+    sleepers    : AtomicUsize,
+    /// 尚未被消费的唤醒次数
+    wakes       : AtomicUsize,
+    closed      : AtomicBool,
+    sleep       : Mutex<()>,
+    wakeup      : Condvar,
+}
+
+impl StealingQueue {
+    /// 每次从全局队列最多领取的任务数
+    const BATCH: usize = 32;
+
+    fn new(workers: usize, aging: Option<Duration>) -> StealingQueue {
+        StealingQueue {
+            injector: Mutex::new(PriorityHeap::new(aging)),
+            locals: RwLock::new((0..workers).map(|_| Mutex::new(VecDeque::new())).collect()),
+            pending: AtomicUsize::new(0),
+            sleepers: AtomicUsize::new(0),
+            wakes: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            sleep: Mutex::new(()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// 保证编号为 `worker` 的本地队列存在
+    fn ensure_local(&self, worker: usize) {
+        if worker < self.locals.read().unwrap_or_else(PoisonError::into_inner).len() {
+            return;
+        }
+        let mut locals = self.locals.write().unwrap_or_else(PoisonError::into_inner);
+        while locals.len() <= worker {
+            locals.push(Mutex::new(VecDeque::new()));
+        }
+    }
+
+    /// 不阻塞地按优先级寻找一个任务，取出时在持锁期间减少计数
+    fn find(&self, worker: usize) -> Option<Job> {
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(job) = self.take(&locals[worker], true) {
+            return Some(job);
+        }
+
+        {
+            let mut injector = lock(&self.injector);
+            if let Some(job) = injector.pop() {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                // 额外领取一批到本地队列，减少对全局队列的争用
+                let n = (injector.len() / locals.len()).min(Self::BATCH);
+                if n > 0 {
+                    let mut local = lock(&locals[worker]);
+                    // 本地队列从尾部取，按优先级逆序放入
+                    let batch: Vec<_> = (0..n).filter_map(|_| injector.pop_entry()).collect();
+                    local.extend(batch.into_iter().rev());
+                }
+                return Some(job);
+            }
+        }
+
+        let len = locals.len();
+        (1..len)
+            .map(|i| (worker + i) % len)
+            .find_map(|victim| self.take(&locals[victim], false))
+    }
+
+    /// 从本地队列取任务，所有者从尾部取，窃取者从头部取
+    fn take(&self, deque: &Mutex<LocalDeque>, owner: bool) -> Option<Job> {
+        let mut deque = lock(deque);
+        let job = if owner { deque.pop_back() } else { deque.pop_front() };
+        if job.is_some() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        job.map(|(_, job)| job)
+    }
+
+    /// 消费一次唤醒
+    fn take_wake(&self) -> bool {
+        self.wakes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |w| w.checked_sub(1))
+            .is_ok()
+    }
+}
+
+impl JobQueue for StealingQueue {
+    fn push(&self, job: Job, key: u64, priority: i32, local: Option<usize>) -> Result<(), Job> {
+        // 先计数再检查关闭标志，保证关闭后工作线程不会漏掉已接受的任务
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self.closed.load(Ordering::SeqCst) {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(job);
+        }
+        {
+            let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+            match local {
+                Some(i) if i < locals.len() && priority == DEFAULT_PRIORITY => {
+                    lock(&locals[i]).push_back((key, job))
+                },
+                _ => lock(&self.injector).push(job, key, priority),
+            }
+        }
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            // 持锁通知，避免与准备休眠的线程错过唤醒
+            let _sleep = lock(&self.sleep);
+            self.wakeup.notify_one();
+        }
+        Ok(())
+    }
+
+    fn pop(&self, worker: usize, timeout: Duration) -> Pop {
+        self.ensure_local(worker);
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(job) = self.find(worker) {
+                return Pop::Job(job);
+            }
+
+            {
+                let mut sleep = lock(&self.sleep);
+                self.sleepers.fetch_add(1, Ordering::SeqCst);
+                let mut timed_out = false;
+                while self.pending.load(Ordering::SeqCst) == 0
+                    && !self.closed.load(Ordering::SeqCst)
+                    && self.wakes.load(Ordering::SeqCst) == 0
+                {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        timed_out = true;
+                        break;
+                    }
+                    sleep = self.wakeup
+                        .wait_timeout(sleep, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                if self.closed.load(Ordering::SeqCst) && self.pending.load(Ordering::SeqCst) == 0 {
+                    return Pop::Closed;
+                }
+                if timed_out || self.take_wake() {
+                    return Pop::Idle;
+                }
+            }
+            // 任务可能刚被计数还未放入队列，或已被其他线程领走，让出时间片后重试
+            thread::yield_now();
+        }
+    }
+
+    fn wake(&self, n: usize) {
+        self.wakes.fetch_add(n, Ordering::SeqCst);
+        let _sleep = lock(&self.sleep);
+        self.wakeup.notify_all();
+    }
+
+    fn retire(&self, worker: usize) {
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(local) = locals.get(worker) {
+            let jobs: Vec<_> = lock(local).drain(..).collect();
+            if !jobs.is_empty() {
+                let mut injector = lock(&self.injector);
+                for (key, job) in jobs {
+                    injector.push(job, key, DEFAULT_PRIORITY);
+                }
+                drop(injector);
+                let _sleep = lock(&self.sleep);
+                self.wakeup.notify_all();
+            }
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _sleep = lock(&self.sleep);
+        self.wakeup.notify_all();
+    }
+
+    fn drain(&self) -> Vec<Job> {
+        let mut jobs = lock(&self.injector).drain();
+        for local in self.locals.read().unwrap_or_else(PoisonError::into_inner).iter() {
+            jobs.extend(lock(local).drain(..).map(|(_, job)| job));
+        }
+        self.pending.fetch_sub(jobs.len(), Ordering::SeqCst);
+        jobs
+    }
+
+    fn pop_oldest(&self) -> Option<Job> {
+        // 全局队列中的任务最早，其次是各本地队列的头部
+        if let Some(job) = lock(&self.injector).pop_oldest() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Some(job);
+        }
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        locals.iter().find_map(|local| self.take(local, false))
+    }
+
+    fn remove(&self, key: u64) -> Option<Job> {
+        let job = lock(&self.injector).remove(key).or_else(|| {
+            let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+            locals.iter().find_map(|local| {
+                let mut local = lock(local);
+                let index = local.iter().position(|(k, _)| *k == key)?;
+                local.remove(index).map(|(_, job)| job)
+            })
+        });
+        if job.is_some() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        job
+    }
+}
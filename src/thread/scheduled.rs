@@ -0,0 +1,343 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{lock, Job, ThreadPool};
+
+/// 定时任务的句柄，可用于取消
+#[derive(Debug, Clone)]
+pub struct ScheduledHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduledHandle {
+    /// 取消任务，尚未开始的执行不会再发生，正在执行的不受影响
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已被取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+enum Kind {
+    Once(Job),
+    /// 以固定频率执行，下次时间从上次计划时间算起
+    FixedRate(Duration, Box<dyn FnMut() + Send + 'static>),
+    /// 以固定间隔执行，下次时间从上次结束时算起
+    FixedDelay(Duration, Box<dyn FnMut() + Send + 'static>),
+}
+
+struct Timed {
+    at          : Instant,
+    seq         : u64,
+    kind        : Kind,
+    cancelled   : Arc<AtomicBool>,
+}
+
+impl PartialEq for Timed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Timed {}
+
+impl PartialOrd for Timed {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timed {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+struct TimerState {
+    heap        : BinaryHeap<Reverse<Timed>>,
+    seq         : u64,
+    shutdown    : bool,
+}
+
+/// 定时线程与工作线程共享的状态
+struct Timer {
+    state       : Mutex<TimerState>,
+    cond        : Condvar,
+}
+
+impl Timer {
+    fn insert(&self, at: Instant, kind: Kind, cancelled: Arc<AtomicBool>) {
+        let mut state = lock(&self.state);
+        if state.shutdown {
+            return;
+        }
+        state.seq += 1;
+        let seq = state.seq;
+        state.heap.push(Reverse(Timed { at, seq, kind, cancelled }));
+        drop(state);
+        self.cond.notify_one();
+    }
+
+    /// 定时线程主循环，到期的任务交给线程池执行
+    fn run(self: Arc<Self>, pool: Arc<ThreadPool>) {
+        let mut state = lock(&self.state);
+        loop {
+            if state.shutdown {
+                break;
+            }
+            let now = Instant::now();
+            let next = state.heap.peek().map(|t| t.0.at);
+            match next {
+                Some(at) if at <= now => {
+                    let Reverse(timed) = state.heap.pop().unwrap();
+                    drop(state);
+                    if !timed.cancelled.load(Ordering::SeqCst) {
+                        let job = Self::into_job(&self, timed);
+                        // 线程池已关闭时直接丢弃
+                        let _ = pool.execute(job);
+                    }
+                    state = lock(&self.state);
+                },
+                Some(at) => {
+                    state = self.cond
+                        .wait_timeout(state, at - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                },
+                None => {
+                    state = self.cond.wait(state).unwrap_or_else(PoisonError::into_inner);
+                },
+            }
+        }
+    }
+
+    /// 把到期的任务包装为线程池任务，周期任务执行完后重新放回定时器
+    ///
+    /// 周期任务 panic 后不再继续执行
+    fn into_job(timer: &Arc<Self>, timed: Timed) -> Job {
+        let Timed { at, kind, cancelled, .. } = timed;
+        match kind {
+            Kind::Once(job) => job,
+            Kind::FixedRate(period, mut f) => {
+                let timer = Arc::clone(timer);
+                Box::new(move || {
+                    f();
+                    if !cancelled.load(Ordering::SeqCst) {
+                        timer.insert(at + period, Kind::FixedRate(period, f), cancelled);
+                    }
+                })
+            },
+            Kind::FixedDelay(delay, mut f) => {
+                let timer = Arc::clone(timer);
+                Box::new(move || {
+                    f();
+                    if !cancelled.load(Ordering::SeqCst) {
+                        timer.insert(Instant::now() + delay, Kind::FixedDelay(delay, f), cancelled);
+                    }
+                })
+            },
+        }
+    }
+}
+
+/// 定时线程池
+///
+/// 由一个定时线程管理到期时间，到期后把任务交给内部的 `ThreadPool` 执行。
+/// 同一个周期任务不会并发执行，某次执行超时时后续执行会顺延
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use ptstd::thread::ScheduledThreadPool;
+///
+/// let pool = ScheduledThreadPool::new(2);
+/// let h = pool.schedule_at_fixed_rate(Duration::ZERO, Duration::from_millis(10), || {
+///     println!("tick");
+/// });
+/// std::thread::sleep(Duration::from_millis(35));
+/// h.cancel();
+/// ```
+pub struct ScheduledThreadPool {
+    pool        : Arc<ThreadPool>,
+    timer       : Arc<Timer>,
+    thread      : Option<JoinHandle<()>>,
+}
+
+impl ScheduledThreadPool {
+    /// 创建有 `max_worker` 个工作线程的定时线程池
+    pub fn new(max_worker: usize) -> ScheduledThreadPool {
+        Self::with_pool(ThreadPool::new(max_worker))
+    }
+
+    /// 使用已有的线程池执行任务
+    pub fn with_pool(pool: ThreadPool) -> ScheduledThreadPool {
+        let pool = Arc::new(pool);
+        let timer = Arc::new(Timer {
+            state: Mutex::new(TimerState {
+                heap: BinaryHeap::new(),
+                seq: 0,
+                shutdown: false,
+            }),
+            cond: Condvar::new(),
+        });
+        let thread = {
+            let timer = Arc::clone(&timer);
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || timer.run(pool))
+        };
+        ScheduledThreadPool { pool, timer, thread: Some(thread) }
+    }
+
+    /// 内部的线程池，可直接提交普通任务
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    /// 延迟 `delay` 后执行一次
+    pub fn schedule<F>(&self, delay: Duration, f: F) -> ScheduledHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.insert(delay, Kind::Once(Box::new(f)))
+    }
+
+    /// 延迟 `initial_delay` 后以固定频率执行，第 n 次计划在 `initial_delay + n * period` 时执行
+    pub fn schedule_at_fixed_rate<F>(&self, initial_delay: Duration, period: Duration, f: F) -> ScheduledHandle
+    where
+        F: FnMut() + Send + 'static,
+    {
+        if period.is_zero() {
+            panic!("period should not be zero");
+        }
+        self.insert(initial_delay, Kind::FixedRate(period, Box::new(f)))
+    }
+
+    /// 延迟 `initial_delay` 后执行，之后每次在上次执行结束 `delay` 后再执行
+    pub fn schedule_with_fixed_delay<F>(&self, initial_delay: Duration, delay: Duration, f: F) -> ScheduledHandle
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.insert(initial_delay, Kind::FixedDelay(delay, Box::new(f)))
+    }
+
+    fn insert(&self, delay: Duration, kind: Kind) -> ScheduledHandle {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.timer.insert(Instant::now() + delay, kind, Arc::clone(&cancelled));
+        ScheduledHandle { cancelled }
+    }
+
+    /// 停止定时线程，丢弃尚未到期的任务，并等待已交给线程池的任务执行完
+    pub fn shutdown(&self) {
+        self.stop_timer();
+        self.pool.shutdown();
+    }
+
+    fn stop_timer(&self) {
+        let mut state = lock(&self.timer.state);
+        state.shutdown = true;
+        state.heap.clear();
+        drop(state);
+        self.timer.cond.notify_all();
+    }
+}
+
+impl Drop for ScheduledThreadPool {
+    fn drop(&mut self) {
+        self.stop_timer();
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_schedule() {
+        let pool = ScheduledThreadPool::new(2);
+        let (tx, rx) = mpsc::channel();
+        let start = Instant::now();
+        let tx2 = tx.clone();
+        pool.schedule(Duration::from_millis(60), move || tx2.send(2).unwrap());
+        pool.schedule(Duration::from_millis(20), move || tx.send(1).unwrap());
+        assert_eq!(rx.recv().unwrap(), 1);
+        assert_eq!(rx.recv().unwrap(), 2);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let pool = ScheduledThreadPool::new(1);
+        let cnt = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&cnt);
+        let h = pool.schedule(Duration::from_millis(30), move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        h.cancel();
+        assert!(h.is_cancelled());
+        thread::sleep(Duration::from_millis(80));
+        assert_eq!(cnt.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_fixed_rate() {
+        let pool = ScheduledThreadPool::new(2);
+        let cnt = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&cnt);
+        let h = pool.schedule_at_fixed_rate(Duration::ZERO, Duration::from_millis(20), move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(150));
+        h.cancel();
+        let n = cnt.load(Ordering::SeqCst);
+        assert!(n >= 3, "ran {} times", n);
+        thread::sleep(Duration::from_millis(60));
+        // 取消后最多还有一次已交给线程池的执行
+        assert!(cnt.load(Ordering::SeqCst) <= n + 1);
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let pool = ScheduledThreadPool::new(2);
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let r = Arc::clone(&runs);
+        let h = pool.schedule_with_fixed_delay(Duration::ZERO, Duration::from_millis(20), move || {
+            r.lock().unwrap().push(Instant::now());
+            thread::sleep(Duration::from_millis(20));
+        });
+        thread::sleep(Duration::from_millis(200));
+        h.cancel();
+        let runs = runs.lock().unwrap();
+        assert!(runs.len() >= 2);
+        // 相邻两次开始时间至少相隔执行时间加间隔
+        for w in runs.windows(2) {
+            assert!(w[1] - w[0] >= Duration::from_millis(40));
+        }
+    }
+
+    #[test]
+    fn test_periodic_panic_stops() {
+        let pool = ScheduledThreadPool::new(1);
+        let cnt = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&cnt);
+        pool.schedule_at_fixed_rate(Duration::ZERO, Duration::from_millis(10), move || {
+            c.fetch_add(1, Ordering::SeqCst);
+            panic!("periodic failed");
+        });
+        thread::sleep(Duration::from_millis(80));
+        assert_eq!(cnt.load(Ordering::SeqCst), 1);
+        // 线程池仍然可用
+        assert_eq!(pool.pool().submit(|| 5).join().unwrap(), 5);
+    }
+}
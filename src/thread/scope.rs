@@ -0,0 +1,193 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use super::{lock, Job, ThreadPool};
+
+/// 作用域，由 `ThreadPool::scope` 创建
+///
+/// 通过 `spawn` 提交的任务可以借用作用域外的数据，`scope` 返回前所有任务都会执行完
+pub struct Scope<'scope, 'env: 'scope> {
+    pool        : &'scope ThreadPool,
+    inner       : Arc<Inner>,
+    scope       : PhantomData<&'scope mut &'scope ()>,
+    env         : PhantomData<&'env mut &'env ()>,
+}
+
+struct State {
+    /// 尚未开始的任务
+    jobs        : VecDeque<Job>,
+    /// 尚未结束的任务数
+    pending     : usize,
+    /// 第一个 panic 的任务的载荷
+    panic       : Option<Box<dyn Any + Send>>,
+}
+
+struct Inner {
+    state       : Mutex<State>,
+    /// 任务结束或有新任务时通知等待中的调用方
+    changed     : Condvar,
+}
+
+impl Inner {
+    fn pop(&self) -> Option<Job> {
+        lock(&self.state).jobs.pop_front()
+    }
+
+    /// 执行一个任务，记录 panic 并更新计数
+    fn run(&self, job: Job) {
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+        let mut state = lock(&self.state);
+        if let Err(e) = result {
+            state.panic.get_or_insert(e);
+        }
+        state.pending -= 1;
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    /// 等待所有任务结束，等待期间调用方也参与执行，
+    /// 因此在工作线程中调用或线程池已关闭时也不会死锁
+    fn wait(&self) {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                self.run(job);
+                state = lock(&self.state);
+            } else if state.pending == 0 {
+                break;
+            } else {
+                state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// 在线程池中执行一个可以借用作用域外数据的任务
+    ///
+    /// 任务中的 panic 会在所有任务结束后从 `scope` 中重新抛出
+    pub fn spawn<F>(&'scope self, f: F) where F: FnOnce() + Send + 'scope {
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(f);
+        // SAFETY: `scope` 返回前会等待 `pending` 归零，任务不会活过 'scope
+        let job: Job = unsafe { std::mem::transmute(job) };
+        let mut state = lock(&self.inner.state);
+        state.jobs.push_back(job);
+        state.pending += 1;
+        drop(state);
+        self.inner.changed.notify_all();
+
+        // 线程池中只放一个取任务的凭据，任务本身留在作用域内，
+        // 凭据执行时任务可能已被调用方取走，此时什么也不做
+        let inner = Arc::clone(&self.inner);
+        let _ = self.pool.try_execute(move || {
+            if let Some(job) = inner.pop() {
+                inner.run(job);
+            }
+        });
+    }
+}
+
+pub(crate) fn run<'env, F, R>(pool: &ThreadPool, f: F) -> R
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+{
+    let scope = Scope {
+        pool,
+        inner: Arc::new(Inner {
+            state: Mutex::new(State { jobs: VecDeque::new(), pending: 0, panic: None }),
+            changed: Condvar::new(),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    // 即使 `f` panic 也要先等待已提交的任务结束
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    scope.inner.wait();
+    let job_panic = lock(&scope.inner.state).panic.take();
+    match (result, job_panic) {
+        (Err(e), _) | (Ok(_), Some(e)) => panic::resume_unwind(e),
+        (Ok(r), None) => r,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_scope_borrow() {
+        let pool = ThreadPool::new(4);
+        let data: Vec<u64> = (1..=1000).collect();
+        let mut sums = [0u64; 4];
+        pool.scope(|s| {
+            for (chunk, sum) in data.chunks(250).zip(sums.iter_mut()) {
+                s.spawn(move || *sum = chunk.iter().sum());
+            }
+        });
+        assert_eq!(sums.iter().sum::<u64>(), 500500);
+    }
+
+    #[test]
+    fn test_scope_nested_spawn() {
+        let pool = ThreadPool::new(2);
+        let cnt = AtomicUsize::new(0);
+        pool.scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    cnt.fetch_add(1, Ordering::SeqCst);
+                    s.spawn(|| { cnt.fetch_add(1, Ordering::SeqCst); });
+                });
+            }
+        });
+        assert_eq!(cnt.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn test_scope_in_worker() {
+        // 单线程的池中在工作线程内使用作用域也不会死锁
+        let pool = Arc::new(ThreadPool::new(1));
+        let p = Arc::clone(&pool);
+        let h = pool.submit(move || {
+            let mut v = [0; 8];
+            p.scope(|s| {
+                for (i, x) in v.iter_mut().enumerate() {
+                    s.spawn(move || *x = i * 2);
+                }
+            });
+            v.iter().sum::<usize>()
+        });
+        assert_eq!(h.join().unwrap(), 56);
+    }
+
+    #[test]
+    fn test_scope_panic() {
+        let pool = ThreadPool::new(2);
+        let cnt = AtomicUsize::new(0);
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.spawn(|| panic!("scoped job failed"));
+                for _ in 0..10 {
+                    s.spawn(|| { cnt.fetch_add(1, Ordering::SeqCst); });
+                }
+            })
+        }));
+        assert!(r.is_err());
+        // panic 前其余任务都已执行完
+        assert_eq!(cnt.load(Ordering::SeqCst), 10);
+        assert_eq!(pool.submit(|| 1).join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_scope_after_shutdown() {
+        let pool = ThreadPool::new(2);
+        pool.shutdown();
+        let mut x = 0;
+        pool.scope(|s| s.spawn(|| x = 1));
+        assert_eq!(x, 1);
+    }
+}
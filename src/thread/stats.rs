@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::Shared;
+
+/// 线程池运行状态的快照，由 `ThreadPool::stats` 返回
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    /// 排队中的任务数
+    pub queued      : usize,
+    /// 正在执行的任务数
+    pub active      : usize,
+    /// 已正常执行完的任务数
+    pub completed   : u64,
+    /// 因队列已满触发拒绝策略的次数
+    pub rejected    : u64,
+    /// 执行时 panic 的任务数
+    pub panicked    : u64,
+    /// 存活的工作线程，按编号排序
+    pub workers     : Vec<WorkerStats>,
+}
+
+/// 单个工作线程的统计
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStats {
+    /// 工作线程编号
+    pub id          : usize,
+    /// 执行任务的累计时间
+    pub busy        : Duration,
+    /// 执行过的任务数，包括 panic 的任务
+    pub jobs        : u64,
+}
+
+/// 工作线程的计数器，线程因 panic 被重新拉起时沿用
+#[derive(Debug, Default)]
+pub(crate) struct WorkerCounters {
+    busy_nanos  : AtomicU64,
+    jobs        : AtomicU64,
+}
+
+impl WorkerCounters {
+    pub(crate) fn record(&self, busy: Duration) {
+        self.busy_nanos.fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+        self.jobs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, id: usize) -> WorkerStats {
+        WorkerStats {
+            id,
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+            jobs: self.jobs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) type StatsHook = dyn Fn(&PoolStats) + Send + Sync + 'static;
+
+/// 周期性调用统计钩子的线程
+pub(crate) struct Reporter {
+    stop        : Arc<AtomicBool>,
+    thread      : JoinHandle<()>,
+}
+
+impl Reporter {
+    /// 只持有线程池的弱引用，不会阻止线程池释放
+    pub(crate) fn spawn(shared: Weak<Shared>, interval: Duration, hook: Box<StatsHook>) -> Reporter {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut next = Instant::now() + interval;
+                loop {
+                    let now = Instant::now();
+                    if now < next {
+                        thread::park_timeout(next - now);
+                    }
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if Instant::now() < next {
+                        continue;
+                    }
+                    let Some(shared) = shared.upgrade() else { break };
+                    if shared.shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    hook(&shared.stats());
+                    next += interval;
+                }
+            })
+        };
+        Reporter { stop, thread }
+    }
+
+    /// 通知线程退出，不等待正在执行的钩子
+    pub(crate) fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.thread().unpark();
+    }
+}
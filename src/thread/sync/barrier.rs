@@ -0,0 +1,145 @@
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use crate::thread::lock;
+
+type BarrierAction = dyn Fn() + Send + Sync + 'static;
+
+struct State {
+    /// 本轮已到达的线程数
+    arrived     : usize,
+    /// 每轮结束时加一，用于区分不同轮次的等待方
+    generation  : u64,
+}
+
+/// 可重复使用的屏障
+///
+/// 每凑齐 `parties` 个线程调用 `wait` 就放行一轮，并在放行前由最后到达的线程执行一次动作
+///
+/// # Example
+/// ```
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use ptstd::thread::sync::CyclicBarrier;
+///
+/// let rounds = Arc::new(AtomicUsize::new(0));
+/// let r = Arc::clone(&rounds);
+/// let barrier = Arc::new(CyclicBarrier::with_action(2, move || { r.fetch_add(1, Ordering::SeqCst); }));
+/// let b = Arc::clone(&barrier);
+/// let t = std::thread::spawn(move || { b.wait(); b.wait(); });
+/// barrier.wait();
+/// barrier.wait();
+/// t.join().unwrap();
+/// assert_eq!(rounds.load(Ordering::SeqCst), 2);
+/// ```
+pub struct CyclicBarrier {
+    parties     : usize,
+    action      : Option<Box<BarrierAction>>,
+    state       : Mutex<State>,
+    cond        : Condvar,
+}
+
+impl fmt::Debug for CyclicBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CyclicBarrier").field("parties", &self.parties).finish_non_exhaustive()
+    }
+}
+
+impl CyclicBarrier {
+    /// 创建每轮需要 `parties` 个线程的屏障，`parties` 为 0 时 panic
+    pub fn new(parties: usize) -> CyclicBarrier {
+        Self::build(parties, None)
+    }
+
+    /// 每轮放行前由最后到达的线程执行 `action`
+    pub fn with_action<F>(parties: usize, action: F) -> CyclicBarrier where F: Fn() + Send + Sync + 'static {
+        Self::build(parties, Some(Box::new(action)))
+    }
+
+    fn build(parties: usize, action: Option<Box<BarrierAction>>) -> CyclicBarrier {
+        if parties == 0 {
+            panic!("parties should not be zero");
+        }
+        CyclicBarrier {
+            parties,
+            action,
+            state: Mutex::new(State { arrived: 0, generation: 0 }),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// 每轮需要的线程数
+    pub fn parties(&self) -> usize {
+        self.parties
+    }
+
+    /// 阻塞直到本轮的线程全部到达，最后到达的线程返回 `true`
+    ///
+    /// 动作在持锁时执行，动作 panic 时本轮照常放行，panic 传给最后到达的线程
+    pub fn wait(&self) -> bool {
+        let mut state = lock(&self.state);
+        state.arrived += 1;
+        if state.arrived == self.parties {
+            // 先放行再抛出动作中的 panic，避免其他线程永远等待
+            let result = self.action.as_ref().map(|action| panic::catch_unwind(AssertUnwindSafe(action)));
+            state.arrived = 0;
+            state.generation += 1;
+            drop(state);
+            self.cond.notify_all();
+            if let Some(Err(e)) = result {
+                panic::resume_unwind(e);
+            }
+            return true;
+        }
+        let generation = state.generation;
+        while state.generation == generation {
+            state = self.cond.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_rounds() {
+        const PARTIES: usize = 8;
+        const ROUNDS: usize = 50;
+        let counter = Arc::new(AtomicUsize::new(0));
+        let actions = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&counter);
+        let a = Arc::clone(&actions);
+        let barrier = Arc::new(CyclicBarrier::with_action(PARTIES, move || {
+            // 动作执行时本轮所有线程都已到达
+            assert_eq!(c.load(Ordering::SeqCst) % PARTIES, 0);
+            a.fetch_add(1, Ordering::SeqCst);
+        }));
+        let leaders = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..PARTIES)
+            .map(|_| {
+                let (barrier, counter, leaders) = (Arc::clone(&barrier), Arc::clone(&counter), Arc::clone(&leaders));
+                thread::spawn(move || {
+                    for round in 0..ROUNDS {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        if barrier.wait() {
+                            leaders.fetch_add(1, Ordering::SeqCst);
+                        }
+                        // 放行后本轮的计数已全部完成
+                        assert!(counter.load(Ordering::SeqCst) >= (round + 1) * PARTIES);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(actions.load(Ordering::SeqCst), ROUNDS);
+        assert_eq!(leaders.load(Ordering::SeqCst), ROUNDS);
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::thread::lock;
+
+/// 倒计时门闩
+///
+/// 计数减到 0 后所有等待方被唤醒，之后的 `wait` 立即返回，计数不能重置
+///
+/// # Example
+/// ```
+/// use std::sync::Arc;
+/// use ptstd::thread::ThreadPool;
+/// use ptstd::thread::sync::CountDownLatch;
+///
+/// let pool = ThreadPool::new(2);
+/// let latch = Arc::new(CountDownLatch::new(3));
+/// for _ in 0..3 {
+///     let latch = Arc::clone(&latch);
+///     pool.execute(move || latch.count_down()).unwrap();
+/// }
+/// latch.wait();
+/// assert_eq!(latch.count(), 0);
+/// ```
+#[derive(Debug)]
+pub struct CountDownLatch {
+    count       : Mutex<usize>,
+    zero        : Condvar,
+}
+
+impl CountDownLatch {
+    /// 创建计数为 `count` 的门闩
+    pub fn new(count: usize) -> CountDownLatch {
+        CountDownLatch { count: Mutex::new(count), zero: Condvar::new() }
+    }
+
+    /// 计数减一，已经为 0 时不做任何事
+    pub fn count_down(&self) {
+        let mut count = lock(&self.count);
+        if *count > 0 {
+            *count -= 1;
+            if *count == 0 {
+                self.zero.notify_all();
+            }
+        }
+    }
+
+    /// 当前计数
+    pub fn count(&self) -> usize {
+        *lock(&self.count)
+    }
+
+    /// 阻塞直到计数为 0
+    pub fn wait(&self) {
+        let mut count = lock(&self.count);
+        while *count > 0 {
+            count = self.zero.wait(count).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// 最多等待 `timeout`，返回计数是否已为 0
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut count = lock(&self.count);
+        while *count > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            count = self.zero
+                .wait_timeout(count, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_latch() {
+        let latch = Arc::new(CountDownLatch::new(8));
+        assert!(!latch.wait_timeout(Duration::from_millis(10)));
+        let waiters: Vec<_> = (0..4)
+            .map(|_| {
+                let latch = Arc::clone(&latch);
+                thread::spawn(move || latch.wait())
+            })
+            .collect();
+        let counters: Vec<_> = (0..8)
+            .map(|_| {
+                let latch = Arc::clone(&latch);
+                thread::spawn(move || {
+                    latch.count_down();
+                    // 多余的 count_down 不会使计数下溢
+                    latch.count_down();
+                })
+            })
+            .collect();
+        for t in counters.into_iter().chain(waiters) {
+            t.join().unwrap();
+        }
+        assert_eq!(latch.count(), 0);
+        assert!(latch.wait_timeout(Duration::ZERO));
+    }
+}
@@ -0,0 +1,13 @@
+//! 线程同步原语
+//!
+//! 可以与线程池配合使用，例如用 `Semaphore::acquire_owned` 限制同时执行的任务数，
+//! 用 `RateLimiter` 限制提交任务的速率
+
+mod barrier;
+mod latch;
+mod rate_limiter;
+mod semaphore;
+pub use barrier::CyclicBarrier;
+pub use latch::CountDownLatch;
+pub use rate_limiter::RateLimiter;
+pub use semaphore::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
@@ -0,0 +1,142 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::thread::lock;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens      : f64,
+    last        : Instant,
+}
+
+/// 令牌桶限速器
+///
+/// 令牌以固定速率生成，桶满时不再增加，获取时消耗令牌，不足时等待或失败
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use ptstd::thread::ThreadPool;
+/// use ptstd::thread::sync::RateLimiter;
+///
+/// // 每 10 毫秒最多提交 1 个任务
+/// let pool = ThreadPool::new(2);
+/// let limiter = RateLimiter::new(1, Duration::from_millis(10));
+/// for i in 0..3 {
+///     limiter.acquire();
+///     pool.execute(move || println!("job {}", i)).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// 每秒生成的令牌数
+    rate        : f64,
+    capacity    : f64,
+    bucket      : Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// 每 `period` 生成 `permits` 个令牌，桶容量为 `permits`，初始为满
+    ///
+    /// `permits` 为 0 或 `period` 为 0 时 panic
+    pub fn new(permits: u32, period: Duration) -> RateLimiter {
+        Self::with_burst(permits, period, permits)
+    }
+
+    /// 指定桶容量，即空闲后一次最多能连续获取的令牌数
+    pub fn with_burst(permits: u32, period: Duration, burst: u32) -> RateLimiter {
+        if permits == 0 || period.is_zero() {
+            panic!("rate should not be zero");
+        }
+        if burst == 0 {
+            panic!("burst should not be zero");
+        }
+        RateLimiter {
+            rate: permits as f64 / period.as_secs_f64(),
+            capacity: burst as f64,
+            bucket: Mutex::new(Bucket { tokens: burst as f64, last: Instant::now() }),
+        }
+    }
+
+    /// 取走 `n` 个令牌，不足时返回还需等待的时间
+    fn take(&self, n: u32) -> Result<(), Duration> {
+        let n = n as f64;
+        if n > self.capacity {
+            panic!("permits exceed burst");
+        }
+        let mut bucket = lock(&self.bucket);
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.capacity);
+        bucket.last = now;
+        if bucket.tokens >= n {
+            bucket.tokens -= n;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((n - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// 获取一个令牌，不足时阻塞
+    pub fn acquire(&self) {
+        self.acquire_many(1);
+    }
+
+    /// 获取 `n` 个令牌，不足时阻塞，`n` 超过桶容量时 panic
+    pub fn acquire_many(&self, n: u32) {
+        while let Err(wait) = self.take(n) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// 不阻塞地获取一个令牌
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_many(1)
+    }
+
+    /// 不阻塞地获取 `n` 个令牌，`n` 超过桶容量时 panic
+    pub fn try_acquire_many(&self, n: u32) -> bool {
+        self.take(n).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_burst() {
+        let limiter = RateLimiter::with_burst(1, Duration::from_secs(10), 3);
+        assert!((0..3).all(|_| limiter.try_acquire()));
+        assert!(!limiter.try_acquire());
+        assert!(!limiter.try_acquire_many(2));
+    }
+
+    #[test]
+    fn test_rate_under_contention() {
+        // 每 10 毫秒一个令牌，桶容量 1
+        let limiter = Arc::new(RateLimiter::new(1, Duration::from_millis(10)));
+        let acquired = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let (limiter, acquired) = (Arc::clone(&limiter), Arc::clone(&acquired));
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        limiter.acquire();
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(acquired.load(Ordering::SeqCst), 20);
+        // 第一个令牌初始就有，其余 19 个至少需要 190 毫秒
+        assert!(start.elapsed() >= Duration::from_millis(185));
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::thread::lock;
+
+/// 计数信号量
+///
+/// 获取到的许可以守卫的形式返回，守卫释放时归还许可
+///
+/// # Example
+/// ```
+/// use std::sync::Arc;
+/// use ptstd::thread::ThreadPool;
+/// use ptstd::thread::sync::Semaphore;
+///
+/// // 最多同时执行 2 个任务，其余的在提交方阻塞
+/// let pool = ThreadPool::new(4);
+/// let sem = Arc::new(Semaphore::new(2));
+/// for i in 0..8 {
+///     let permit = sem.acquire_owned();
+///     pool.execute(move || {
+///         let _permit = permit;
+///         println!("job {}", i);
+///     }).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Semaphore {
+    permits     : Mutex<usize>,
+    released    : Condvar,
+}
+
+impl Semaphore {
+    /// 创建有 `permits` 个许可的信号量
+    pub fn new(permits: usize) -> Semaphore {
+        Semaphore { permits: Mutex::new(permits), released: Condvar::new() }
+    }
+
+    /// 当前可用的许可数
+    pub fn available_permits(&self) -> usize {
+        *lock(&self.permits)
+    }
+
+    /// 增加 `n` 个许可
+    pub fn add_permits(&self, n: usize) {
+        *lock(&self.permits) += n;
+        self.released.notify_all();
+    }
+
+    /// 阻塞直到获取一个许可
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// 阻塞直到一次获取 `n` 个许可
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        self.take(n, None);
+        SemaphorePermit { sem: self, n }
+    }
+
+    /// 不阻塞地获取一个许可
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.take(1, Some(Instant::now())).then(|| SemaphorePermit { sem: self, n: 1 })
+    }
+
+    /// 最多等待 `timeout` 获取一个许可
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<SemaphorePermit<'_>> {
+        self.take(1, Some(Instant::now() + timeout)).then(|| SemaphorePermit { sem: self, n: 1 })
+    }
+
+    /// 获取一个不借用信号量的许可，可以移动到线程池的任务中
+    pub fn acquire_owned(self: &Arc<Self>) -> OwnedSemaphorePermit {
+        self.take(1, None);
+        OwnedSemaphorePermit { sem: Arc::clone(self), n: 1 }
+    }
+
+    /// 不阻塞地获取一个不借用信号量的许可
+    pub fn try_acquire_owned(self: &Arc<Self>) -> Option<OwnedSemaphorePermit> {
+        self.take(1, Some(Instant::now())).then(|| OwnedSemaphorePermit { sem: Arc::clone(self), n: 1 })
+    }
+
+    /// 等待直到有 `n` 个许可并取走，超过 `deadline` 返回 `false`
+    fn take(&self, n: usize, deadline: Option<Instant>) -> bool {
+        let mut permits = lock(&self.permits);
+        while *permits < n {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    permits = self.released
+                        .wait_timeout(permits, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                },
+                None => {
+                    permits = self.released.wait(permits).unwrap_or_else(PoisonError::into_inner);
+                },
+            }
+        }
+        *permits -= n;
+        true
+    }
+}
+
+/// 借用信号量的许可，释放时归还
+#[derive(Debug)]
+pub struct SemaphorePermit<'a> {
+    sem         : &'a Semaphore,
+    n           : usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// 不归还许可，相当于永久减少信号量的许可数
+    pub fn forget(mut self) {
+        self.n = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.n > 0 {
+            self.sem.add_permits(self.n);
+        }
+    }
+}
+
+/// 持有信号量所有权的许可，释放时归还
+#[derive(Debug)]
+pub struct OwnedSemaphorePermit {
+    sem         : Arc<Semaphore>,
+    n           : usize,
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.sem.add_permits(self.n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread::ThreadPool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_contention() {
+        let sem = Arc::new(Semaphore::new(3));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..16)
+            .map(|_| {
+                let (sem, running, peak) = (Arc::clone(&sem), Arc::clone(&running), Arc::clone(&peak));
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        let _permit = sem.acquire();
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        thread::yield_now();
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(sem.available_permits(), 3);
+    }
+
+    #[test]
+    fn test_try_and_timeout() {
+        let sem = Semaphore::new(2);
+        let two = sem.acquire_many(2);
+        assert!(sem.try_acquire().is_none());
+        assert!(sem.acquire_timeout(Duration::from_millis(10)).is_none());
+        drop(two);
+        let one = sem.try_acquire().unwrap();
+        one.forget();
+        assert_eq!(sem.available_permits(), 1);
+    }
+
+    #[test]
+    fn test_throttle_pool() {
+        let pool = ThreadPool::new(4);
+        let sem = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let permit = sem.acquire_owned();
+            let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+            pool.execute(move || {
+                let _permit = permit;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(2));
+                running.fetch_sub(1, Ordering::SeqCst);
+            }).unwrap();
+        }
+        pool.shutdown();
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert!(sem.try_acquire_owned().is_some());
+    }
+}
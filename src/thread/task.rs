@@ -0,0 +1,109 @@
+use std::any::Any;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::channel::{oneshot, RecvTimeoutError, TryRecvError};
+use super::deterministic;
+
+/// 等待任务结果时的错误
+#[derive(Debug, Error)]
+pub enum JoinError {
+    /// 任务执行时发生 panic，内容为 panic 信息
+    #[error("task panicked: {0}")]
+    Panicked(String),
+    /// 等待超时，任务仍在执行或排队
+    #[error("task join timed out")]
+    Timeout,
+    /// 任务在完成前被丢弃，不会再有结果
+    #[error("task was dropped before completion")]
+    Canceled,
+    /// 在确定性模式的任务中等待未完成的任务，任务只在调用方线程中执行，永远不会完成
+    #[error("task joined from inside a deterministic job")]
+    Deadlock,
+}
+
+/// 任务句柄
+///
+/// 由 `ThreadPool::submit` 返回，用于取回任务的返回值。
+/// 任务中的 panic 会被捕获并以 `JoinError::Panicked` 的形式返回。
+pub struct TaskHandle<R> {
+    receiver     : oneshot::Receiver<Result<R, String>>,
+    deterministic: bool,
+}
+
+impl<R> TaskHandle<R> {
+    pub(crate) fn new(receiver: oneshot::Receiver<Result<R, String>>) -> TaskHandle<R> {
+        TaskHandle { receiver, deterministic: false }
+    }
+
+    /// 标记任务由确定性模式的线程池执行
+    pub(crate) fn deterministic(mut self, deterministic: bool) -> TaskHandle<R> {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// 阻塞等待任务完成
+    ///
+    /// 在确定性模式的任务中等待同样由确定性模式执行、尚未完成的任务时，
+    /// 不会阻塞而是返回 `JoinError::Deadlock`
+    pub fn join(self) -> Result<R, JoinError> {
+        if self.deterministic && deterministic::in_job() {
+            return self.try_join()?.ok_or(JoinError::Deadlock);
+        }
+        match self.receiver.recv() {
+            Ok(r) => r.map_err(JoinError::Panicked),
+            Err(_) => Err(JoinError::Canceled),
+        }
+    }
+
+    /// 不阻塞地查询任务结果，任务未完成时返回 `Ok(None)`
+    pub fn try_join(&self) -> Result<Option<R>, JoinError> {
+        match self.receiver.try_recv() {
+            Ok(r) => r.map(Some).map_err(JoinError::Panicked),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(JoinError::Canceled),
+        }
+    }
+
+    /// 最多等待 `timeout`，超时返回 `JoinError::Timeout`，句柄仍可继续等待
+    pub fn join_timeout(&self, timeout: Duration) -> Result<R, JoinError> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(r) => r.map_err(JoinError::Panicked),
+            Err(RecvTimeoutError::Timeout) => Err(JoinError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(JoinError::Canceled),
+        }
+    }
+}
+
+/// 从 panic 的载荷中取出信息
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_message() {
+        let e = std::panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        assert_eq!(panic_message(e.as_ref()), "boom");
+        let e = std::panic::catch_unwind(|| panic!("{} {}", "boom", 1)).unwrap_err();
+        assert_eq!(panic_message(e.as_ref()), "boom 1");
+    }
+
+    #[test]
+    fn test_canceled() {
+        let (tx, rx) = oneshot::channel::<Result<i32, String>>();
+        let handle = TaskHandle::new(rx);
+        drop(tx);
+        assert!(matches!(handle.join(), Err(JoinError::Canceled)));
+    }
+}
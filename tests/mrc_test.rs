@@ -27,7 +27,7 @@ impl<T> Node<T> {
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
         println!("list drop");
-        while let Some(_) = self.pop_back() {
+        while self.pop_back().is_some() {
 
         }
     }
@@ -0,0 +1,16 @@
+//! 全局 logger 每个进程只能设置一次，写文件的测试单独放在一个测试进程中
+use log::{set_max_level, LevelFilter};
+use ptstd::log::slog::*;
+
+#[test]
+fn basic_file() {
+    SLog::init_with_filename("test.log", LevelFilter::Trace).unwrap();
+    set_max_level(LevelFilter::Debug);
+
+    trace!("some trace");
+    debug!("some debug");
+    info!("some info");
+    warn!("some warn");
+    error!("some error");
+    debug!(target: "my_target", "a {} event", "log");
+}